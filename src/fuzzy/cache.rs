@@ -0,0 +1,446 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::mem;
+use std::process;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use fuzzy::directory_scanner::DirectoryScanner;
+use fuzzy::options::ScannerOptions;
+use fuzzy::watcher::Change;
+
+const HEADER: &'static str = "fuzzy index 2";
+// threads reading directories while the cache is revalidated
+const WORKERS: usize = 8;
+// caches of roots that weren't searched for this long are removed
+const MAX_UNUSED_SECONDS: i64 = 30 * 24 * 60 * 60;
+// and so are files left behind by saves that never finished
+const MAX_TEMPORARY_SECONDS: i64 = 24 * 60 * 60;
+
+// saves of this process so far, so that no two share a temporary file
+static SAVES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CacheMode {
+    Use,
+    // start from an empty cache and save what the scan finds
+    Rebuild,
+    Off,
+}
+
+// A scanned directory: when it was last modified, if that can be trusted, the stamp of the
+// ignore files that applied to it, and the names of the results and the scanned directories
+// directly in it. Results that are directories keep their trailing `/`.
+#[derive(Clone, Debug, PartialEq)]
+struct CachedDirectory {
+    modified: Option<(i64, i64)>,
+    ignores: u64,
+    files: Vec<OsString>,
+    directories: Vec<OsString>,
+}
+
+// What a scan of one root with one set of scanner options found, kept between runs.
+// Stored as a header line with the root and the options, then a `modified.ignores<TAB>directory`
+// line for each directory followed by `f<TAB>name` and `d<TAB>name` lines for its entries.
+pub struct IndexCache {
+    path: Option<PathBuf>,
+    root_dir: PathBuf,
+    options: Arc<ScannerOptions>,
+    directories: HashMap<OsString, CachedDirectory>,
+}
+
+impl IndexCache {
+
+    // A cache that can't be read, or was written for something else, starts out empty.
+    pub fn load(cache_dir: Option<PathBuf>, root_dir: &Path, options: Arc<ScannerOptions>) -> IndexCache {
+        let header = header(root_dir, &options);
+        let path = cache_dir.map(|cache_dir| cache_dir.join(file_name(&header)));
+        let mut content = vec![];
+        if let Some(ref path) = path {
+            let _ = File::open(path).and_then(|mut file| file.read_to_end(&mut content));
+        }
+        let directories = if content.split(|byte| *byte == b'\n').next() == Some(&header[..]) { parse_directories(&content) } else { HashMap::new() };
+        IndexCache { path: path, root_dir: root_dir.to_path_buf(), options: options, directories: directories }
+    }
+
+    // `$XDG_CACHE_HOME/fuzzy`, or `~/.cache/fuzzy`.
+    pub fn default_dir() -> Option<PathBuf> {
+        let cache_home = match env::var("XDG_CACHE_HOME") {
+            Ok(dir) => Some(PathBuf::from(dir)),
+            Err(_) => env::var("HOME").ok().map(|home| PathBuf::from(home).join(".cache")),
+        };
+        cache_home.map(|cache_home| cache_home.join("fuzzy"))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.directories.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.directories.clear();
+    }
+
+    // Every cached result, as the absolute paths `DirectoryScanner` sends.
+    pub fn files(&self) -> Vec<OsString> {
+        let mut files = vec![];
+        for (dir, cached) in self.directories.iter() {
+            files.extend(cached.files.iter().map(|name| join(dir, name)));
+        }
+        files
+    }
+
+    // Walks the tree like a scan does, on several threads, but only reads the directories
+    // that were modified, or whose ignore files were, since they were cached. What changed is
+    // sent to `subscriber` one directory at a time, so an empty cache sends every file, like
+    // a scan.
+    pub fn revalidate(&mut self, subscriber: Option<&Sender<Vec<Change>>>) {
+        let started = now();
+        // the scanners only read directories here, they never send anything
+        let (tx, _) = mpsc::channel();
+        let walk = Walk {
+            pending: vec![DirectoryScanner::new(self.root_dir.clone(), Arc::new(Mutex::new(tx)), self.options.clone())],
+            reading: 0,
+            cached: mem::replace(&mut self.directories, HashMap::new()),
+            directories: HashMap::new(),
+        };
+        let walk = Arc::new((Mutex::new(walk), Condvar::new()));
+        let workers: Vec<_> = (0..WORKERS).map(|_| {
+            let walk = walk.clone();
+            let subscriber = subscriber.cloned();
+            thread::spawn(move || revalidate_directories(&walk, subscriber.as_ref(), started))
+        }).collect();
+        for worker in workers {
+            let _ = worker.join();
+        }
+        let mut walk = walk.0.lock().unwrap();
+        self.directories = mem::replace(&mut walk.directories, HashMap::new());
+    }
+
+    // Written next to the cache and moved over it, so it's never left half written, even
+    // when several processes save the same cache. Caches that weren't used for a while are
+    // removed on the way.
+    pub fn save(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            try!(fs::create_dir_all(dir));
+            prune(dir, path);
+        }
+        let save = SAVES.fetch_add(1, Ordering::SeqCst);
+        let temporary_path = path.with_extension(format!("{}.{}.tmp", process::id(), save));
+        {
+            let mut file = BufWriter::new(try!(File::create(&temporary_path)));
+            try!(file.write_all(&header(&self.root_dir, &self.options)));
+            try!(file.write_all(b"\n"));
+            for (dir, cached) in self.directories.iter() {
+                if has_newline(dir) {
+                    continue;
+                }
+                // a name that can't be stored on its own line means the listing is incomplete
+                let storable = !cached.files.iter().chain(cached.directories.iter()).any(|name| has_newline(name));
+                match cached.modified {
+                    Some((seconds, nanoseconds)) if storable => try!(write!(file, "{}.{}.{:x}\t", seconds, nanoseconds, cached.ignores)),
+                    _ => try!(write!(file, "-\t")),
+                }
+                try!(write_line(&mut file, dir));
+                for name in cached.files.iter().filter(|name| !has_newline(name)) {
+                    try!(file.write_all(b"f\t"));
+                    try!(write_line(&mut file, name));
+                }
+                for name in cached.directories.iter().filter(|name| !has_newline(name)) {
+                    try!(file.write_all(b"d\t"));
+                    try!(write_line(&mut file, name));
+                }
+            }
+            try!(file.flush());
+        }
+        fs::rename(&temporary_path, path)
+    }
+}
+
+fn header(root_dir: &Path, options: &ScannerOptions) -> Vec<u8> {
+    let mut header = format!("{}\t{}\t", HEADER, options.to_key()).into_bytes();
+    header.extend(root_dir.as_os_str().as_bytes());
+    header
+}
+
+// Named by a hash that stays the same across builds and Rust versions.
+fn file_name(header: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in header.iter() {
+        hash = (hash ^ *byte as u64).wrapping_mul(0x100000001b3);
+    }
+    format!("index-{:016x}", hash)
+}
+
+// Removes the caches, other than `keep`, that weren't saved for a long time, and the
+// temporary files of saves that didn't finish.
+fn prune(cache_dir: &Path, keep: &Path) {
+    let entries = match fs::read_dir(cache_dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    let now = now();
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.as_bytes();
+        if !name.starts_with(b"index-") || path == keep {
+            continue;
+        }
+        let max_age = if name.ends_with(b".tmp") { MAX_TEMPORARY_SECONDS } else { MAX_UNUSED_SECONDS };
+        match entry.metadata() {
+            Ok(ref metadata) if metadata.is_file() && metadata.mtime() as i64 + max_age < now => {
+                let _ = fs::remove_file(&path);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn parse_directories(content: &[u8]) -> HashMap<OsString, CachedDirectory> {
+    let mut directories = HashMap::new();
+    let mut current: Option<(OsString, CachedDirectory)> = None;
+    for line in content.split(|byte| *byte == b'\n').skip(1) {
+        let (kind, value) = match line.iter().position(|byte| *byte == b'\t') {
+            Some(index) => (&line[..index], OsStr::from_bytes(&line[(index + 1)..]).to_os_string()),
+            None => continue,
+        };
+        match (kind, current.as_mut()) {
+            (b"f", Some(&mut (_, ref mut directory))) => directory.files.push(value),
+            (b"d", Some(&mut (_, ref mut directory))) => directory.directories.push(value),
+            (b"f", None) | (b"d", None) => {}
+            (modified, _) => {
+                if let Some((dir, directory)) = current.take() {
+                    directories.insert(dir, directory);
+                }
+                let (modified, ignores) = parse_modified(&String::from_utf8_lossy(modified)).unwrap_or((None, 0));
+                let directory = CachedDirectory { modified: modified, ignores: ignores, files: vec![], directories: vec![] };
+                current = Some((value, directory));
+            }
+        }
+    }
+    if let Some((dir, directory)) = current {
+        directories.insert(dir, directory);
+    }
+    directories
+}
+
+fn parse_modified(modified: &str) -> Option<(Option<(i64, i64)>, u64)> {
+    let parts: Vec<&str> = modified.splitn(3, '.').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+    match (parts[0].parse(), parts[1].parse(), u64::from_str_radix(parts[2], 16)) {
+        (Ok(seconds), Ok(nanoseconds), Ok(ignores)) => Some((Some((seconds, nanoseconds)), ignores)),
+        _ => None,
+    }
+}
+
+// What the threads revalidating a cache share: the directories left to look at, how many
+// are being looked at and may still add more, and the cached directories before and after.
+struct Walk {
+    pending: Vec<DirectoryScanner>,
+    reading: usize,
+    cached: HashMap<OsString, CachedDirectory>,
+    directories: HashMap<OsString, CachedDirectory>,
+}
+
+// Takes directories off the walk until none are left and no other thread can add any.
+fn revalidate_directories(walk: &(Mutex<Walk>, Condvar), subscriber: Option<&Sender<Vec<Change>>>, started: i64) {
+    let (ref walk, ref more) = *walk;
+    loop {
+        let (scanner, cached) = {
+            let mut walk = walk.lock().unwrap();
+            while walk.pending.is_empty() && walk.reading > 0 {
+                walk = more.wait(walk).unwrap();
+            }
+            let scanner = match walk.pending.pop() {
+                Some(scanner) => scanner,
+                None => return,
+            };
+            walk.reading += 1;
+            let cached = walk.cached.remove(scanner.root_dir().as_os_str());
+            (scanner, cached)
+        };
+        let (directory, children) = revalidate_directory(&scanner, cached, subscriber, started);
+        let mut walk = walk.lock().unwrap();
+        walk.reading -= 1;
+        if let Some(directory) = directory {
+            walk.directories.insert(scanner.root_dir().as_os_str().to_os_string(), directory);
+        }
+        walk.pending.extend(children);
+        more.notify_all();
+    }
+}
+
+// The directory as it is now, unless it's gone, and scanners for the directories below it.
+fn revalidate_directory(scanner: &DirectoryScanner, cached: Option<CachedDirectory>, subscriber: Option<&Sender<Vec<Change>>>, started: i64) -> (Option<CachedDirectory>, Vec<DirectoryScanner>) {
+    let dir = scanner.root_dir().as_os_str();
+    let modified = modified_time(scanner.root_dir());
+    let ignores = scanner.ignore_stamp();
+    match cached {
+        Some(cached) if cached.modified.is_some() && cached.modified == modified && cached.ignores == ignores => {
+            let children = scanner.children(cached.directories.iter().map(|name| PathBuf::from(join(dir, name))).collect());
+            (Some(cached), children)
+        }
+        cached => {
+            let (files, children) = match scanner.read_directory() {
+                Some(listing) => listing,
+                None => {
+                    if cached.is_some() {
+                        send(subscriber, vec![Change::Removed(dir.to_os_string())]);
+                    }
+                    return (None, vec![]);
+                }
+            };
+            let cached = cached.unwrap_or(CachedDirectory { modified: None, ignores: 0, files: vec![], directories: vec![] });
+            let directory = CachedDirectory {
+                // changes within the same second as the scan could go unnoticed
+                modified: modified.and_then(|modified| if modified.0 < started - 1 { Some(modified) } else { None }),
+                ignores: ignores,
+                files: files.iter().map(|file| name(Path::new(file))).collect(),
+                directories: children.iter().map(|child| name(child.root_dir())).collect(),
+            };
+            send(subscriber, changes(dir, &cached, &directory));
+            (Some(directory), children)
+        }
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs() as i64).unwrap_or(0)
+}
+
+fn modified_time(dir: &Path) -> Option<(i64, i64)> {
+    fs::metadata(dir).ok().map(|metadata| (metadata.mtime() as i64, metadata.mtime_nsec() as i64))
+}
+
+// Files that are new to a directory, and files and directories that are gone from it.
+fn changes(dir: &OsStr, cached: &CachedDirectory, directory: &CachedDirectory) -> Vec<Change> {
+    let mut changes = vec![];
+    let cached_files: HashSet<&OsString> = cached.files.iter().collect();
+    let added: Vec<OsString> = directory.files.iter().filter(|name| !cached_files.contains(name)).map(|name| join(dir, name)).collect();
+    if !added.is_empty() {
+        changes.push(Change::Added(added));
+    }
+    let files: HashSet<&OsString> = directory.files.iter().collect();
+    let directories: HashSet<&OsString> = directory.directories.iter().collect();
+    for name in cached.files.iter().filter(|name| !files.contains(name)) {
+        changes.push(Change::Removed(join(dir, name)));
+    }
+    for name in cached.directories.iter().filter(|name| !directories.contains(name)) {
+        changes.push(Change::Removed(join(dir, name)));
+    }
+    changes
+}
+
+fn send(subscriber: Option<&Sender<Vec<Change>>>, changes: Vec<Change>) {
+    if let Some(subscriber) = subscriber {
+        if !changes.is_empty() {
+            let _ = subscriber.send(changes);
+        }
+    }
+}
+
+fn join(dir: &OsStr, name: &OsStr) -> OsString {
+    Path::new(dir).join(name).into_os_string()
+}
+
+fn name(path: &Path) -> OsString {
+    let mut name = path.file_name().map_or(OsString::new(), |name| name.to_os_string());
+    if path.as_os_str().as_bytes().ends_with(b"/") {
+        name.push("/");
+    }
+    name
+}
+
+fn has_newline(name: &OsStr) -> bool {
+    name.as_bytes().contains(&b'\n')
+}
+
+fn write_line<W: Write>(out: &mut W, value: &OsStr) -> io::Result<()> {
+    try!(out.write_all(value.as_bytes()));
+    out.write_all(b"\n")
+}
+
+#[test]
+fn only_modified_directories_are_read_again() {
+    let cache_dir = env::temp_dir().join(format!("fuzzy_cache_test_{}", process::id()));
+    let root_dir = cache_dir.join("root");
+    let _ = fs::remove_dir_all(&cache_dir);
+    fs::create_dir_all(root_dir.join("unchanged")).unwrap();
+    fs::create_dir_all(root_dir.join("gone")).unwrap();
+    for file in ["first", "unchanged/second", "gone/third"].iter() {
+        File::create(root_dir.join(file)).unwrap();
+    }
+    let options = Arc::new(ScannerOptions::new());
+    let mut cache = IndexCache::load(Some(cache_dir.clone()), &root_dir, options.clone());
+    assert!(cache.is_empty());
+    cache.revalidate(None);
+    assert_eq!(cache.files().len(), 5);
+    // just modified directories are never trusted, so trust this one by hand
+    let unchanged = root_dir.join("unchanged").into_os_string();
+    cache.directories.get_mut(&unchanged).unwrap().modified = modified_time(&root_dir.join("unchanged"));
+    cache.directories.get_mut(&unchanged).unwrap().files.push(OsString::from("only_in_the_cache"));
+    cache.save().unwrap();
+
+    fs::remove_dir_all(root_dir.join("gone")).unwrap();
+    File::create(root_dir.join("new")).unwrap();
+    let mut cache = IndexCache::load(Some(cache_dir.clone()), &root_dir, options.clone());
+    assert_eq!(cache.files().len(), 6);
+    let (tx, rx) = mpsc::channel();
+    cache.revalidate(Some(&tx));
+    let mut changes: Vec<Change> = rx.try_iter().flat_map(|changes| changes.into_iter()).collect();
+    changes.sort_by_key(|change| format!("{:?}", change));
+    let path = |file: &str| root_dir.join(file).into_os_string();
+    assert_eq!(changes, vec![Change::Added(vec![path("new")]), Change::Removed(path("gone")), Change::Removed(path("gone/"))]);
+    let mut files = cache.files();
+    files.sort();
+    assert_eq!(files, vec![path("first"), path("new"), path("unchanged/"), path("unchanged/only_in_the_cache"), path("unchanged/second")]);
+    let _ = fs::remove_dir_all(&cache_dir);
+}
+
+#[test]
+fn directories_are_read_again_when_their_ignore_files_change() {
+    let cache_dir = env::temp_dir().join(format!("fuzzy_cache_ignore_test_{}", process::id()));
+    let root_dir = cache_dir.join("root");
+    let _ = fs::remove_dir_all(&cache_dir);
+    fs::create_dir_all(root_dir.join("sub")).unwrap();
+    for file in ["sub/kept.rs", "sub/build.log"].iter() {
+        File::create(root_dir.join(file)).unwrap();
+    }
+    File::create(root_dir.join(".ignore")).unwrap().write_all(b"*.rs\n").unwrap();
+    let mut cache = IndexCache::load(None, &root_dir, Arc::new(ScannerOptions::new()));
+    cache.revalidate(None);
+    for dir in [root_dir.clone(), root_dir.join("sub")].iter() {
+        cache.directories.get_mut(dir.as_os_str()).unwrap().modified = modified_time(dir);
+    }
+
+    // rewriting the file in place leaves the modification time of the directories alone
+    File::create(root_dir.join(".ignore")).unwrap().write_all(b"*.log\n").unwrap();
+    let (tx, rx) = mpsc::channel();
+    cache.revalidate(Some(&tx));
+    let mut changes: Vec<Change> = rx.try_iter().flat_map(|changes| changes.into_iter()).collect();
+    changes.sort_by_key(|change| format!("{:?}", change));
+    let path = |file: &str| root_dir.join(file).into_os_string();
+    assert_eq!(changes, vec![Change::Added(vec![path("sub/kept.rs")]), Change::Removed(path("sub/build.log"))]);
+    let _ = fs::remove_dir_all(&cache_dir);
+}
+
+#[test]
+fn cache_files_are_named_by_a_stable_hash() {
+    // FNV-1a, which doesn't change between builds like the standard library's hasher may
+    assert_eq!(file_name(b""), "index-cbf29ce484222325");
+    assert_eq!(file_name(b"a"), "index-af63dc4c8601ec8c");
+}
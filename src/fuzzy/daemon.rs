@@ -0,0 +1,579 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::mem;
+use std::net::Shutdown;
+use std::os::raw::c_uint;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use fuzzy::directory_scanner::DirectoryScanner;
+use fuzzy::file_finder;
+use fuzzy::options::{ScannerOptions, KEY_FIELDS};
+use fuzzy::watcher::{Change, TreeWatcher};
+
+const RESPONSE_HEADER: &'static [u8] = b"ok\n";
+// the files are followed by the changes to them, see `subscribe`
+const WATCHING_HEADER: &'static [u8] = b"watching\n";
+const WATCH_PREFIX: &'static str = "watch\t";
+// How long a client waits for the whole list before scanning by itself. A daemon asked
+// for a new root carries on scanning it, so the next request finds it ready.
+const REQUEST_TIMEOUT_MS: u64 = 2000;
+
+extern "C" {
+    fn getuid() -> c_uint;
+}
+
+// The files below one root for one set of scanner options. Once asked for they are kept
+// current by a watcher, without one every request scans again. Subscribers are sent every
+// change, `files` is locked first.
+struct Index {
+    root_dir: PathBuf,
+    options: Arc<ScannerOptions>,
+    files: Mutex<Option<HashSet<OsString>>>,
+    subscribers: Mutex<Vec<Sender<Vec<Change>>>>,
+}
+
+// Serves the files below registered roots to clients on a Unix domain socket. A client
+// sends one request line, see `encode_request`, and reads `ok` and a newline followed by
+// NUL terminated absolute paths. Roots are registered when first requested.
+//
+// A request line starting with `watch` and a tab asks for the changes as well. When the
+// root is kept current the answer starts with `watching` instead, the files end with an
+// empty path and every change follows as a NUL terminated record: `+` and an added file,
+// `-` and a removed path, or a lone `!` when the daemon lost track and stops sending.
+pub struct Daemon {
+    indexes: Mutex<HashMap<String, Arc<Index>>>,
+}
+
+impl Daemon {
+
+    pub fn new() -> Daemon {
+        Daemon { indexes: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn files(&self, root_dir: &Path, options: &ScannerOptions) -> Vec<OsString> {
+        list_files(&self.index(root_dir, options), false).0
+    }
+
+    pub fn listen(daemon: Arc<Daemon>, listener: UnixListener) {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let daemon = daemon.clone();
+                    thread::spawn(move || {
+                        let _ = daemon.serve(stream);
+                    });
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    // ------- private methods -------- //
+
+    fn index(&self, root_dir: &Path, options: &ScannerOptions) -> Arc<Index> {
+        let mut indexes = self.indexes.lock().unwrap();
+        indexes.entry(encode_request(root_dir, options)).or_insert_with(|| {
+            Arc::new(Index { root_dir: root_dir.to_path_buf(), options: Arc::new(options.clone()), files: Mutex::new(None), subscribers: Mutex::new(vec![]) })
+        }).clone()
+    }
+
+    fn serve(&self, stream: UnixStream) -> io::Result<()> {
+        if !credentials::is_own_user(&stream) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "client runs as another user"));
+        }
+        try!(stream.set_read_timeout(Some(Duration::from_millis(REQUEST_TIMEOUT_MS))));
+        let mut line = String::new();
+        try!(BufReader::new(&stream).read_line(&mut line));
+        let (watch, line) = if line.starts_with(WATCH_PREFIX) { (true, &line[WATCH_PREFIX.len()..]) } else { (false, &line[..]) };
+        let (root_dir, options) = match parse_request(line) {
+            Some(request) => request,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid request")),
+        };
+        if !root_dir.is_dir() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "not a directory"));
+        }
+        let (files, changes) = list_files(&self.index(&root_dir, &options), watch);
+        let mut writer = BufWriter::new(&stream);
+        try!(writer.write_all(if changes.is_some() { WATCHING_HEADER } else { RESPONSE_HEADER }));
+        for file in files {
+            try!(writer.write_all(file.as_os_str().as_bytes()));
+            try!(writer.write_all(b"\0"));
+        }
+        let changes = match changes {
+            Some(changes) => changes,
+            None => return writer.flush(),
+        };
+        try!(writer.write_all(b"\0"));
+        try!(writer.flush());
+        // until the index is dropped, or the client hangs up
+        for changes in changes.iter() {
+            for change in changes {
+                try!(write_change(&mut writer, &change));
+            }
+            try!(writer.flush());
+        }
+        Ok(())
+    }
+}
+
+// `$XDG_RUNTIME_DIR/fuzzy.sock`, or `fuzzy.sock` in a directory of the temporary directory
+// only this user can get into.
+pub fn socket_path() -> PathBuf {
+    match env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) => PathBuf::from(dir).join("fuzzy.sock"),
+        Err(_) => env::temp_dir().join(format!("fuzzy-{}", unsafe { getuid() })).join("fuzzy.sock"),
+    }
+}
+
+// The files below `root_dir` from the daemon listening on `socket`, `None` when no daemon
+// is running there, it couldn't list them in time, or the socket or the daemon belong to
+// another user who could make up any paths.
+pub fn request_files(socket: &Path, root_dir: &Path, options: &ScannerOptions) -> Option<Vec<OsString>> {
+    request(socket, root_dir, options, encode_request(root_dir, options)).map(|(files, _)| files)
+}
+
+// Like `request_files`, along with the changes to the files from then on when the daemon
+// keeps the root current.
+pub fn subscribe(socket: &Path, root_dir: &Path, options: &ScannerOptions) -> Option<(Vec<OsString>, Option<Subscription>)> {
+    request(socket, root_dir, options, format!("{}{}", WATCH_PREFIX, encode_request(root_dir, options)))
+}
+
+// Changes to the files below a root as the daemon sees them, see `subscribe`. Clones share
+// the connection, so one can `close` it while another waits.
+pub struct Subscription {
+    stream: UnixStream,
+    // the start of a record that hasn't been read to its end yet
+    pending: Vec<u8>,
+    closed: Arc<AtomicBool>,
+}
+
+impl Subscription {
+
+    // Blocks until there are changes. An error once the daemon is gone, a `Rescan` when it
+    // lost track of the root and sends nothing more.
+    pub fn wait(&mut self) -> io::Result<Vec<Change>> {
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let records_end = match self.pending.iter().rposition(|byte| *byte == 0) {
+                Some(position) => position + 1,
+                None => 0,
+            };
+            if records_end > 0 {
+                let rest = self.pending.split_off(records_end);
+                let records = mem::replace(&mut self.pending, rest);
+                return Ok(decode_changes(&records));
+            }
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the daemon stopped")),
+                Ok(length) => self.pending.extend_from_slice(&buffer[..length]),
+                Err(ref error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    // Ends the subscription, `wait` returns an error from then on.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    pub fn try_clone(&self) -> io::Result<Subscription> {
+        let stream = try!(self.stream.try_clone());
+        Ok(Subscription { stream: stream, pending: vec![], closed: self.closed.clone() })
+    }
+}
+
+// Runs the daemon until it's killed, indexing `root_dirs` up front. Returns the exit code.
+pub fn run(root_dirs: &Vec<PathBuf>, options: &ScannerOptions) -> i32 {
+    let path = socket_path();
+    let listener = match bind(&path) {
+        Ok(listener) => listener,
+        Err(error) => {
+            let _ = writeln!(io::stderr(), "fuzzy: could not listen on '{}': {}", path.display(), error);
+            return 1;
+        }
+    };
+    let daemon = Arc::new(Daemon::new());
+    if !root_dirs.is_empty() {
+        for root in file_finder::scan_roots(root_dirs) {
+            let (daemon, options) = (daemon.clone(), options.clone());
+            thread::spawn(move || {
+                daemon.files(&root.root_dir, &options);
+            });
+        }
+    }
+    Daemon::listen(daemon, listener);
+    0
+}
+
+// The scanner options key, see `ScannerOptions::to_key`, then a tab and the root.
+pub fn encode_request(root_dir: &Path, options: &ScannerOptions) -> String {
+    format!("{}\t{}\n", options.to_key(), root_dir.to_string_lossy())
+}
+
+pub fn parse_request(line: &str) -> Option<(PathBuf, ScannerOptions)> {
+    let fields: Vec<&str> = line.trim_right_matches('\n').splitn(KEY_FIELDS + 1, '\t').collect();
+    if fields.len() != KEY_FIELDS + 1 {
+        return None;
+    }
+    ScannerOptions::from_key(&fields[..KEY_FIELDS].join("\t")).map(|options| (PathBuf::from(fields[KEY_FIELDS]), options))
+}
+
+// The files and, for an answer that goes on with changes, what was read of those already.
+fn request(socket: &Path, root_dir: &Path, options: &ScannerOptions, request: String) -> Option<(Vec<OsString>, Option<Subscription>)> {
+    if root_dir.to_str().map_or(true, |root_dir| root_dir.contains('\n')) || !options.is_indexable() {
+        return None;
+    }
+    if !fs::symlink_metadata(socket).map(|metadata| is_own_socket(&metadata)).unwrap_or(false) {
+        return None;
+    }
+    let mut stream = match UnixStream::connect(socket) {
+        Ok(stream) => stream,
+        Err(_) => return None,
+    };
+    if !credentials::is_own_user(&stream) {
+        return None;
+    }
+    let timeout = Duration::from_millis(REQUEST_TIMEOUT_MS);
+    let deadline = Instant::now() + timeout;
+    if stream.set_write_timeout(Some(timeout)).is_err() || stream.write_all(request.as_bytes()).is_err() {
+        return None;
+    }
+    let mut response = vec![];
+    let mut buffer = vec![0; 64 * 1024];
+    // where the empty path that ends the files of a `watching` answer is looked for next
+    let mut searched = WATCHING_HEADER.len();
+    loop {
+        if response.starts_with(WATCHING_HEADER) && response.len() > searched {
+            if let Some(end) = list_end(&response, searched) {
+                let pending = response.split_off(end);
+                if stream.set_read_timeout(None).is_err() {
+                    return None;
+                }
+                let subscription = Subscription { stream: stream, pending: pending, closed: Arc::new(AtomicBool::new(false)) };
+                return Some((decode_files(&response[WATCHING_HEADER.len()..]), Some(subscription)));
+            }
+            searched = response.len() - 1;
+        }
+        let now = Instant::now();
+        if now >= deadline || stream.set_read_timeout(Some(deadline - now)).is_err() {
+            return None;
+        }
+        match stream.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(length) => response.extend_from_slice(&buffer[..length]),
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => return None,
+        }
+    }
+    if !response.starts_with(RESPONSE_HEADER) {
+        return None;
+    }
+    Some((decode_files(&response[RESPONSE_HEADER.len()..]), None))
+}
+
+// Just past the empty path after the header, or the first one found from `from` on.
+fn list_end(response: &[u8], from: usize) -> Option<usize> {
+    if response.len() > WATCHING_HEADER.len() && response[WATCHING_HEADER.len()] == 0 {
+        return Some(WATCHING_HEADER.len() + 1);
+    }
+    response[from..].windows(2).position(|pair| pair == b"\0\0").map(|position| from + position + 2)
+}
+
+fn decode_files(list: &[u8]) -> Vec<OsString> {
+    list.split(|byte| *byte == 0)
+        .filter(|file| !file.is_empty())
+        .map(|file| OsString::from_vec(file.to_vec()))
+        .collect()
+}
+
+fn write_change<W: Write>(writer: &mut W, change: &Change) -> io::Result<()> {
+    match *change {
+        Change::Added(ref files) => {
+            for file in files {
+                try!(writer.write_all(b"+"));
+                try!(writer.write_all(file.as_bytes()));
+                try!(writer.write_all(b"\0"));
+            }
+            Ok(())
+        }
+        Change::Removed(ref path) => {
+            try!(writer.write_all(b"-"));
+            try!(writer.write_all(path.as_bytes()));
+            writer.write_all(b"\0")
+        }
+        Change::Rescan => writer.write_all(b"!\0"),
+    }
+}
+
+// Complete records only, added files that follow each other come as one change.
+fn decode_changes(records: &[u8]) -> Vec<Change> {
+    let mut changes = vec![];
+    for record in records.split(|byte| *byte == 0).filter(|record| !record.is_empty()) {
+        let path = OsString::from_vec(record[1..].to_vec());
+        match (record[0], changes.last_mut()) {
+            (b'+', Some(&mut Change::Added(ref mut files))) => {
+                files.push(path);
+                continue;
+            }
+            _ => {}
+        }
+        match record[0] {
+            b'+' => changes.push(Change::Added(vec![path])),
+            b'-' => changes.push(Change::Removed(path)),
+            b'!' => changes.push(Change::Rescan),
+            _ => {}
+        }
+    }
+    changes
+}
+
+// A socket left behind by a daemon that is gone is replaced, a running one is left alone.
+// The socket is only ever reachable by this user: it is created without permissions for
+// anyone else, in a directory that is made private when it doesn't exist yet.
+fn bind(path: &Path) -> io::Result<UnixListener> {
+    if let Some(dir) = path.parent() {
+        try!(private_dir(dir));
+    }
+    if UnixStream::connect(path).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AddrInUse, "a daemon is already running"));
+    }
+    if fs::symlink_metadata(path).map(|metadata| metadata.file_type().is_socket()).unwrap_or(false) {
+        try!(fs::remove_file(path));
+    }
+    let previous_mask = credentials::set_umask(0o177);
+    let listener = UnixListener::bind(path);
+    credentials::set_umask(previous_mask);
+    listener
+}
+
+// `dir` when it is a directory of this user that nobody else can get into, created so if need be.
+fn private_dir(dir: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(dir) {
+        Ok(metadata) => {
+            let is_private = metadata.is_dir() && metadata.uid() == unsafe { getuid() } && metadata.permissions().mode() & 0o077 == 0;
+            if is_private {
+                Ok(())
+            } else {
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("'{}' can be used by other users", dir.display())))
+            }
+        }
+        Err(_) => fs::DirBuilder::new().mode(0o700).create(dir),
+    }
+}
+
+fn is_own_socket(metadata: &fs::Metadata) -> bool {
+    metadata.file_type().is_socket() && metadata.uid() == unsafe { getuid() }
+}
+
+// The watcher is started before the scan so nothing changing in between is missed. With
+// `subscribe` the changes from then on are sent on the channel, when the index is kept
+// current.
+fn list_files(index: &Arc<Index>, subscribe: bool) -> (Vec<OsString>, Option<Receiver<Vec<Change>>>) {
+    let mut files = index.files.lock().unwrap();
+    if let Some(ref files) = *files {
+        let changes = if subscribe { Some(add_subscriber(index)) } else { None };
+        return (files.iter().cloned().collect(), changes);
+    }
+    let watcher = TreeWatcher::new(&index.root_dir, index.options.clone());
+    let scanned = DirectoryScanner::collect(index.root_dir.clone(), index.options.clone());
+    match watcher {
+        Ok(watcher) => {
+            *files = Some(scanned.iter().cloned().collect());
+            keep_current(index.clone(), watcher);
+            let changes = if subscribe { Some(add_subscriber(index)) } else { None };
+            (scanned, changes)
+        }
+        Err(error) => {
+            warn_unwatched(&index.root_dir, &error);
+            (scanned, None)
+        }
+    }
+}
+
+fn add_subscriber(index: &Arc<Index>) -> Receiver<Vec<Change>> {
+    let (tx, rx) = mpsc::channel();
+    index.subscribers.lock().unwrap().push(tx);
+    rx
+}
+
+// Until events are lost, or a new directory can't be watched, then the index is dropped and
+// the next request scans again. Subscribers are told with a `Rescan` and sent nothing more.
+fn keep_current(index: Arc<Index>, watcher: TreeWatcher) {
+    thread::spawn(move || {
+        loop {
+            let changes = match watcher.wait() {
+                Ok(changes) => changes,
+                Err(error) => {
+                    warn_unwatched(&index.root_dir, &error);
+                    vec![Change::Rescan]
+                }
+            };
+            let mut files = index.files.lock().unwrap();
+            let mut subscribers = index.subscribers.lock().unwrap();
+            if changes.contains(&Change::Rescan) {
+                *files = None;
+                for subscriber in subscribers.drain(..) {
+                    let _ = subscriber.send(vec![Change::Rescan]);
+                }
+                break;
+            }
+            let files = match *files {
+                Some(ref mut files) => files,
+                None => break,
+            };
+            for change in changes.iter() {
+                match *change {
+                    Change::Added(ref added) => files.extend(added.iter().cloned()),
+                    Change::Removed(ref path) => {
+                        let below = |file: &OsString| {
+                            let (file, path) = (file.as_bytes(), path.as_bytes());
+                            file.len() > path.len() && file.starts_with(path) && file[path.len()] == b'/'
+                        };
+                        files.retain(|file| file != path && !below(file));
+                    }
+                    Change::Rescan => {}
+                }
+            }
+            subscribers.retain(|subscriber| subscriber.send(changes.clone()).is_ok());
+        }
+    });
+}
+
+fn warn_unwatched(root_dir: &Path, error: &io::Error) {
+    let _ = writeln!(io::stderr(), "fuzzy: can't keep '{}' current, it is scanned for every request: {}", root_dir.display(), error);
+}
+
+#[cfg(target_os = "linux")]
+mod credentials {
+    use std::mem;
+    use std::os::raw::{c_int, c_uint, c_void};
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixStream;
+
+    const SOL_SOCKET: c_int = 1;
+    const SO_PEERCRED: c_int = 17;
+
+    #[repr(C)]
+    struct PeerCredentials {
+        pid: c_int,
+        uid: c_uint,
+        gid: c_uint,
+    }
+
+    extern "C" {
+        fn getsockopt(socket: c_int, level: c_int, name: c_int, value: *mut c_void, length: *mut c_uint) -> c_int;
+        fn getuid() -> c_uint;
+        fn umask(mask: c_uint) -> c_uint;
+    }
+
+    // Whether the process at the other end of `stream` runs as this user.
+    pub fn is_own_user(stream: &UnixStream) -> bool {
+        let mut credentials = PeerCredentials { pid: 0, uid: 0, gid: 0 };
+        let mut length = mem::size_of::<PeerCredentials>() as c_uint;
+        let result = unsafe {
+            getsockopt(stream.as_raw_fd(), SOL_SOCKET, SO_PEERCRED, &mut credentials as *mut PeerCredentials as *mut c_void, &mut length)
+        };
+        result == 0 && credentials.uid == unsafe { getuid() }
+    }
+
+    pub fn set_umask(mask: u32) -> u32 {
+        unsafe { umask(mask) }
+    }
+}
+
+// Without a way to tell who is at the other end nobody is trusted, so the daemon is never used.
+#[cfg(not(target_os = "linux"))]
+mod credentials {
+    use std::os::unix::net::UnixStream;
+
+    pub fn is_own_user(_: &UnixStream) -> bool {
+        false
+    }
+
+    pub fn set_umask(mask: u32) -> u32 {
+        mask
+    }
+}
+
+#[test]
+fn requests_round_trip() {
+    let mut options = ScannerOptions::new();
+    options.include_hidden = true;
+    options.max_depth = Some(2);
+    options.min_depth = 1;
+    let request = encode_request(Path::new("/projects/with\ttab"), &options);
+    assert_eq!(parse_request(&request), Some((PathBuf::from("/projects/with\ttab"), options)));
+    assert_eq!(parse_request("1\t1\n"), None);
+}
+
+#[test]
+fn files_are_served_over_the_socket() {
+    let socket = env::temp_dir().join(format!("fuzzy_daemon_test_{}", std::process::id())).join("fuzzy.sock");
+    let root_dir = env::current_dir().unwrap().join("test");
+    assert_eq!(request_files(&socket, &root_dir, &ScannerOptions::new()), None);
+    let listener = bind(&socket).unwrap();
+    thread::spawn(move || Daemon::listen(Arc::new(Daemon::new()), listener));
+    let mut files = request_files(&socket, &root_dir, &ScannerOptions::new()).unwrap();
+    files.sort();
+    let expected: Vec<OsString> = ["first", "second", "third_dir/", "third_dir/third"].iter().map(|file| root_dir.join(file).into_os_string()).collect();
+    assert_eq!(files, expected);
+    assert_eq!(request_files(&socket, &root_dir.join("missing"), &ScannerOptions::new()), None);
+    assert_eq!(fs::metadata(&socket).unwrap().permissions().mode() & 0o777, 0o600);
+    assert_eq!(fs::metadata(socket.parent().unwrap()).unwrap().permissions().mode() & 0o777, 0o700);
+    let _ = fs::remove_dir_all(socket.parent().unwrap());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn subscribers_are_sent_the_changes_to_the_files_they_were_listed() {
+    use std::fs::File;
+    let dir = env::temp_dir().join(format!("fuzzy_daemon_subscribe_test_{}", std::process::id()));
+    let (socket, root_dir) = (dir.join("socket").join("fuzzy.sock"), dir.join("root"));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&root_dir).unwrap();
+    let first = root_dir.join("first").into_os_string();
+    File::create(&first).unwrap();
+    let listener = bind(&socket).unwrap();
+    thread::spawn(move || Daemon::listen(Arc::new(Daemon::new()), listener));
+    let (files, subscription) = subscribe(&socket, &root_dir, &ScannerOptions::new()).unwrap();
+    assert_eq!(files, vec![first.clone()]);
+    let mut subscription = subscription.unwrap();
+    let second = root_dir.join("second").into_os_string();
+    File::create(&second).unwrap();
+    assert_eq!(subscription.wait().unwrap(), vec![Change::Added(vec![second.clone()])]);
+    fs::remove_file(&second).unwrap();
+    assert_eq!(subscription.wait().unwrap(), vec![Change::Removed(second)]);
+    assert_eq!(request_files(&socket, &root_dir, &ScannerOptions::new()), Some(vec![first]));
+    subscription.try_clone().unwrap().close();
+    assert!(subscription.wait().is_err() && subscription.is_closed());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn listed_files_end_at_the_first_empty_path() {
+    let header = WATCHING_HEADER.len();
+    assert_eq!(list_end(b"watching\n\0+/root/new\0", header), Some(header + 1));
+    assert_eq!(list_end(b"watching\n/root/a\0/root/b\0\0-/root/a\0", header), Some(header + 17));
+    assert_eq!(list_end(b"watching\n/root/a\0/root/b\0", header), None);
+    assert_eq!(decode_changes(b"+/root/a\0+/root/b/\0-/root/c\0!\0"), vec![
+        Change::Added(vec![OsString::from("/root/a"), OsString::from("/root/b/")]),
+        Change::Removed(OsString::from("/root/c")),
+        Change::Rescan,
+    ]);
+}
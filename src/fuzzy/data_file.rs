@@ -0,0 +1,39 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+// Where what fuzzy remembers between runs is kept: `$XDG_DATA_HOME/fuzzy/<name>`, or
+// `~/.local/share/fuzzy/<name>`.
+pub fn default_path(name: &str) -> Option<PathBuf> {
+    let data_home = match env::var("XDG_DATA_HOME") {
+        Ok(dir) => Some(PathBuf::from(dir)),
+        Err(_) => env::var("HOME").ok().map(|home| PathBuf::from(home).join(".local").join("share")),
+    };
+    data_home.map(|data_home| data_home.join("fuzzy").join(name))
+}
+
+// Empty when there is no file, or it can't be read.
+pub fn read(path: &Option<PathBuf>) -> Vec<u8> {
+    let mut content = vec![];
+    if let Some(ref path) = *path {
+        if File::open(path).and_then(|mut file| file.read_to_end(&mut content)).is_err() {
+            content.clear();
+        }
+    }
+    content
+}
+
+// Written next to `path` and moved over it, so another fuzzy never reads it half written.
+pub fn write(path: &Path, content: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        try!(fs::create_dir_all(dir));
+    }
+    let temporary_path = path.with_extension(format!("{}.tmp", process::id()));
+    {
+        let mut file = try!(File::create(&temporary_path));
+        try!(file.write_all(content));
+    }
+    fs::rename(&temporary_path, path)
+}
@@ -1,73 +1,404 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{self, Sender};
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::fs::{self, PathExt};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
 use std::thread;
+use fuzzy::entry_filter::EntryFilter;
+use fuzzy::ignore::IgnoreStack;
+use fuzzy::options::ScannerOptions;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    // sockets, pipes and devices, never listed
+    Other,
+}
 
 pub struct DirectoryScanner {
     root_dir: PathBuf,
-    subscriber: Arc<Mutex<Sender<Vec<String>>>>,
+    subscriber: Arc<Mutex<Sender<Vec<OsString>>>>,
     concurrency_limit: usize,
+    options: Arc<ScannerOptions>,
+    // made for the root the scan started at, and shared by every directory below it
+    filter: Arc<EntryFilter>,
+    ignore_stack: Option<IgnoreStack>,
+    depth: usize,
+    // (device, inode) of every directory between the root and this one, to stop symlink loops
+    ancestors: Vec<(u64, u64)>,
+    // where the hidden entries left out are recorded, see `record_hidden`
+    hidden: Option<Arc<Mutex<HiddenEntries>>>,
+    // where the symbolic links found are recorded, see `record_links`
+    links: Option<Arc<Mutex<Links>>>,
+}
+
+// The symbolic links scans came across by their path, with where each points as it says
+// and whether nothing is there.
+pub type Links = HashMap<PathBuf, (PathBuf, bool)>;
+
+// The hidden files and directories a scan left out, in the state the scan found them,
+// so they can be added later without walking everything else again.
+pub struct HiddenEntries {
+    files: Vec<OsString>,
+    directories: Vec<DirectoryScanner>,
+}
+
+impl HiddenEntries {
+
+    pub fn new() -> HiddenEntries {
+        HiddenEntries { files: vec![], directories: vec![] }
+    }
+
+    // What a scan with hidden files would have found on top of the scan that left these out.
+    pub fn collect(self) -> Vec<OsString> {
+        let (tx, rx) = mpsc::channel();
+        let subscriber = Arc::new(Mutex::new(tx));
+        let _ = subscriber.lock().unwrap().send(self.files);
+        for scanner in self.directories {
+            let mut options = (*scanner.options).clone();
+            options.include_hidden = true;
+            let mut scanner = DirectoryScanner { subscriber: subscriber.clone(), options: Arc::new(options), ..scanner };
+            thread::spawn(move || {
+                scanner.scan(Arc::new(AtomicUsize::new(0)));
+            });
+        }
+        drop(subscriber);
+        rx.iter().flat_map(|files| files.into_iter()).collect()
+    }
 }
 
 impl DirectoryScanner {
 
-    pub fn new(root_dir: PathBuf, subscriber: Arc<Mutex<Sender<Vec<String>>>>) -> DirectoryScanner {
+    pub fn new(root_dir: PathBuf, subscriber: Arc<Mutex<Sender<Vec<OsString>>>>, options: Arc<ScannerOptions>) -> DirectoryScanner {
+        let filter = Arc::new(EntryFilter::new(&options.filters, &root_dir));
+        DirectoryScanner::with_filter(root_dir, subscriber, options, filter)
+    }
+
+    // A scan of `root_dir` below the root `filter` was made for.
+    pub fn with_filter(root_dir: PathBuf, subscriber: Arc<Mutex<Sender<Vec<OsString>>>>, options: Arc<ScannerOptions>, filter: Arc<EntryFilter>) -> DirectoryScanner {
+        let ignore_stack = if options.respect_ignore_files { Some(IgnoreStack::for_root(&root_dir)) } else { None };
+        let ancestors = if options.follow_symlinks { directory_id(&root_dir).into_iter().collect() } else { vec![] };
         DirectoryScanner{
             root_dir: root_dir,
             subscriber: subscriber,
             concurrency_limit: 9,
+            options: options,
+            filter: filter,
+            ignore_stack: ignore_stack,
+            depth: 0,
+            ancestors: ancestors,
+            hidden: None,
+            links: None,
         }
     }
 
+    // Every file below `root_dir`, once the whole tree was scanned.
+    pub fn collect(root_dir: PathBuf, options: Arc<ScannerOptions>) -> Vec<OsString> {
+        let filter = Arc::new(EntryFilter::new(&options.filters, &root_dir));
+        DirectoryScanner::collect_with_filter(root_dir, options, filter, None)
+    }
+
+    pub fn collect_with_filter(root_dir: PathBuf, options: Arc<ScannerOptions>, filter: Arc<EntryFilter>, links: Option<Arc<Mutex<Links>>>) -> Vec<OsString> {
+        let (tx, rx) = mpsc::channel();
+        let mut scanner = DirectoryScanner::with_filter(root_dir, Arc::new(Mutex::new(tx)), options, filter);
+        scanner.links = links;
+        thread::spawn(move || {
+            scanner.scan(Arc::new(AtomicUsize::new(0)));
+        });
+        rx.iter().flat_map(|files| files.into_iter()).collect()
+    }
+
+    // Hidden entries that aren't ignored are recorded in `hidden` instead of only left out,
+    // here and in every directory below.
+    pub fn record_hidden(&mut self, hidden: Arc<Mutex<HiddenEntries>>) {
+        self.hidden = Some(hidden);
+    }
+
+    // The symbolic links listed or followed are recorded in `links` before they are sent,
+    // here and in every directory below.
+    pub fn record_links(&mut self, links: Arc<Mutex<Links>>) {
+        self.links = Some(links);
+    }
+
     pub fn scan(&mut self, current_concurrency: Arc<AtomicUsize>) {
-        match fs::read_dir(&self.root_dir) {
-            Ok(read_dir) => {
-                let mut filepaths = vec![];
-                for entry in read_dir {
-                    match entry {
-                        Ok(entry) => {
-                            let filetype = entry.file_type().unwrap();
-                            if filetype.is_file() {
-                                filepaths.push(entry.path().to_str().unwrap().to_string());
-                            } else if filetype.is_dir() && !filetype.is_symlink() {
-                                let path = PathBuf::from(entry.path().to_str().unwrap().to_string());
-                                if self.concurrency_limit_reached(&current_concurrency) {
-                                    self.scan_directory(path, current_concurrency.clone());
-                                } else {
-                                    self.scan_directory_within_thread(path, current_concurrency.clone());
-                                }
-                            }
-                        }
-                        Err(_) => { }
+        match self.read_directory() {
+            Some((filepaths, children)) => {
+                for scanner in children {
+                    if self.concurrency_limit_reached(&current_concurrency) {
+                        self.scan_directory(scanner, current_concurrency.clone());
+                    } else {
+                        self.scan_directory_within_thread(scanner, current_concurrency.clone());
                     }
                 }
                 let _ = self.subscriber.lock().unwrap().send(filepaths);
             }
-            Err(_) => { }
+            None => { }
         }
     }
 
+    // The entries directly in this directory, directories with a trailing `/`, and scanners
+    // for the directories below it.
+    pub fn read_directory(&self) -> Option<(Vec<OsString>, Vec<DirectoryScanner>)> {
+        let read_dir = match fs::read_dir(&self.root_dir) {
+            Ok(read_dir) => read_dir,
+            Err(_) => return None,
+        };
+        let mut filepaths = vec![];
+        let mut children = vec![];
+        let mut hidden = HiddenEntries::new();
+        let mut links = vec![];
+        let ignore_stack = self.ignore_stack.as_ref().map(|stack| stack.descend(&self.root_dir));
+        let depth = self.depth + 1;
+        for entry in read_dir {
+            match entry {
+                Ok(entry) => {
+                    let path = entry.path();
+                    let is_left_out = !self.options.include_hidden && is_hidden(&path);
+                    if is_left_out && self.hidden.is_none() {
+                        continue;
+                    }
+                    let filetype = match entry.file_type() {
+                        Ok(filetype) => filetype,
+                        Err(_) => continue,
+                    };
+                    let kind = entry_kind(&path, filetype, self.options.follow_symlinks);
+                    if is_ignored(&ignore_stack, &path, kind == EntryKind::Directory) || self.filter.excludes(&path, kind) {
+                        continue;
+                    }
+                    if filetype.is_symlink() && self.links.is_some() {
+                        links.extend(link(&path));
+                    }
+                    let (files, directories) = if is_left_out { (&mut hidden.files, &mut hidden.directories) } else { (&mut filepaths, &mut children) };
+                    if depth >= self.options.min_depth && self.options.types.includes(kind) && self.filter.lists(&path, kind, self.options.follow_symlinks) {
+                        files.push(result_path(&path, kind));
+                    }
+                    if kind == EntryKind::Directory && self.can_descend(depth) {
+                        directories.extend(self.child(path, ignore_stack.clone(), depth));
+                    }
+                }
+                Err(_) => { }
+            }
+        }
+        if let Some(ref recorded) = self.links {
+            if !links.is_empty() {
+                recorded.lock().unwrap().extend(links);
+            }
+        }
+        if let Some(ref recorded) = self.hidden {
+            if !hidden.files.is_empty() || !hidden.directories.is_empty() {
+                let mut recorded = recorded.lock().unwrap();
+                recorded.files.extend(hidden.files);
+                // sending elsewhere keeps this scan from waiting on them, and they are only
+                // scanned once hidden entries are included, so there's nothing to record then
+                let (tx, _) = mpsc::channel();
+                let subscriber = Arc::new(Mutex::new(tx));
+                recorded.directories.extend(hidden.directories.into_iter().map(|scanner| DirectoryScanner { subscriber: subscriber.clone(), hidden: None, ..scanner }));
+            }
+        }
+        Some((filepaths, children))
+    }
+
+    // Scanners for directories below this one found earlier, without reading it again.
+    pub fn children(&self, paths: Vec<PathBuf>) -> Vec<DirectoryScanner> {
+        let ignore_stack = self.ignore_stack.as_ref().map(|stack| stack.descend(&self.root_dir));
+        let depth = self.depth + 1;
+        paths.into_iter().filter_map(|path| self.child(path, ignore_stack.clone(), depth)).collect()
+    }
+
+    pub fn root_dir(&self) -> &PathBuf {
+        &self.root_dir
+    }
+
+    // Changes when an ignore file that applies to the entries of this directory does.
+    pub fn ignore_stamp(&self) -> u64 {
+        self.ignore_stack.as_ref().map_or(0, |stack| stack.descended_stamp(&self.root_dir))
+    }
+
     //---------- private methods ------------//
 
     fn concurrency_limit_reached(&self, current_concurrency: &Arc<AtomicUsize>) -> bool {
         current_concurrency.load(Ordering::Relaxed) >= self.concurrency_limit
     }
 
-    fn scan_directory(&mut self, path: PathBuf, currency_concurrency: Arc<AtomicUsize>) {
-        let mut scanner = DirectoryScanner::new(path, self.subscriber.clone());
+    // entries of a directory at `depth` are one level deeper still
+    fn can_descend(&self, depth: usize) -> bool {
+        match self.options.max_depth {
+            Some(max_depth) => depth < max_depth,
+            None => true,
+        }
+    }
+
+    // `None` for a directory that is already being scanned further up, a symbolic link loop.
+    fn child(&self, path: PathBuf, ignore_stack: Option<IgnoreStack>, depth: usize) -> Option<DirectoryScanner> {
+        let mut ancestors = self.ancestors.clone();
+        if self.options.follow_symlinks {
+            match directory_id(&path) {
+                Some(id) if ancestors.contains(&id) => return None,
+                Some(id) => ancestors.push(id),
+                None => return None,
+            }
+        }
+        Some(DirectoryScanner {
+            root_dir: path,
+            subscriber: self.subscriber.clone(),
+            concurrency_limit: self.concurrency_limit,
+            options: self.options.clone(),
+            filter: self.filter.clone(),
+            ignore_stack: ignore_stack,
+            depth: depth,
+            ancestors: ancestors,
+            hidden: self.hidden.clone(),
+            links: self.links.clone(),
+        })
+    }
+
+    fn scan_directory(&mut self, mut scanner: DirectoryScanner, currency_concurrency: Arc<AtomicUsize>) {
         scanner.scan(currency_concurrency);
     }
 
-    fn scan_directory_within_thread(&mut self, path: PathBuf, current_concurrency: Arc<AtomicUsize>) {
+    fn scan_directory_within_thread(&mut self, mut scanner: DirectoryScanner, current_concurrency: Arc<AtomicUsize>) {
         current_concurrency.fetch_add(1, Ordering::Relaxed);
-        let subscriber = self.subscriber.clone();
         thread::spawn(move||{
-            let mut scanner = DirectoryScanner::new(path, subscriber);
             scanner.scan(current_concurrency.clone());
             current_concurrency.fetch_sub(1, Ordering::Relaxed);
         });
     }
 }
 
+pub fn is_hidden(path: &PathBuf) -> bool {
+    path.file_name().map_or(false, |name| name.as_bytes().starts_with(b"."))
+}
+
+// A followed link is what it points to, unless that is gone and it shows up as a broken link.
+pub fn entry_kind(path: &PathBuf, filetype: fs::FileType, follow_symlinks: bool) -> EntryKind {
+    if filetype.is_symlink() && follow_symlinks {
+        match fs::metadata(path) {
+            Ok(metadata) => kind(metadata.file_type()),
+            Err(_) => EntryKind::Symlink,
+        }
+    } else {
+        kind(filetype)
+    }
+}
+
+fn kind(filetype: fs::FileType) -> EntryKind {
+    if filetype.is_file() {
+        EntryKind::File
+    } else if filetype.is_dir() {
+        EntryKind::Directory
+    } else if filetype.is_symlink() {
+        EntryKind::Symlink
+    } else {
+        EntryKind::Other
+    }
+}
+
+// Where the symbolic link at `path` points, and whether nothing is there.
+pub fn link(path: &PathBuf) -> Option<(PathBuf, (PathBuf, bool))> {
+    fs::read_link(path).ok().map(|target| (path.clone(), (target, fs::metadata(path).is_err())))
+}
+
+// How an entry is sent as a result, directories marked with a trailing `/`.
+pub fn result_path(path: &PathBuf, kind: EntryKind) -> OsString {
+    let mut result = path.clone().into_os_string();
+    if kind == EntryKind::Directory {
+        result.push("/");
+    }
+    result
+}
+
+fn directory_id(path: &PathBuf) -> Option<(u64, u64)> {
+    fs::metadata(path).ok().map(|metadata| (metadata.dev() as u64, metadata.ino() as u64))
+}
+
+pub fn is_ignored(ignore_stack: &Option<IgnoreStack>, path: &PathBuf, is_dir: bool) -> bool {
+    match *ignore_stack {
+        Some(ref ignore_stack) => {
+            (is_dir && path.file_name().map_or(false, |name| name == ".git")) || ignore_stack.is_ignored(path, is_dir)
+        }
+        None => false,
+    }
+}
+
+#[test]
+fn followed_links_stop_at_loops_and_list_dangling_links() {
+    use std::env;
+    use std::fs::File;
+    use std::os::unix::fs::symlink;
+    let root_dir = env::temp_dir().join(format!("fuzzy_scanner_links_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root_dir);
+    fs::create_dir_all(root_dir.join("shared")).unwrap();
+    File::create(root_dir.join("shared").join("config")).unwrap();
+    symlink(root_dir.join("shared"), root_dir.join("linked")).unwrap();
+    symlink(&root_dir, root_dir.join("shared").join("loop")).unwrap();
+    symlink("missing", root_dir.join("dangling")).unwrap();
+    let mut options = ScannerOptions::new();
+    options.follow_symlinks = true;
+    let mut files = DirectoryScanner::collect(root_dir.clone(), Arc::new(options));
+    files.sort();
+    let expected: Vec<OsString> = ["dangling", "linked/", "linked/config", "linked/loop/", "shared/", "shared/config", "shared/loop/"].iter().map(|file| root_dir.join(file).into_os_string()).collect();
+    assert_eq!(files, expected);
+    // not followed, links are listed as they are, and recorded with where they point
+    let links = Arc::new(Mutex::new(HashMap::new()));
+    let mut files = DirectoryScanner::collect_with_filter(root_dir.clone(), Arc::new(ScannerOptions::new()), Arc::new(EntryFilter::new(&ScannerOptions::new().filters, &root_dir)), Some(links.clone()));
+    files.sort();
+    let expected: Vec<OsString> = ["dangling", "linked", "shared/", "shared/config", "shared/loop"].iter().map(|file| root_dir.join(file).into_os_string()).collect();
+    assert_eq!(files, expected);
+    let links = links.lock().unwrap();
+    assert_eq!(links.len(), 3);
+    assert_eq!(links.get(&root_dir.join("linked")), Some(&(root_dir.join("shared"), false)));
+    assert_eq!(links.get(&root_dir.join("dangling")), Some(&(PathBuf::from("missing"), true)));
+    let _ = fs::remove_dir_all(&root_dir);
+}
+
+#[test]
+fn depth_limits_are_relative_to_the_root() {
+    use std::env;
+    let root_dir = env::current_dir().unwrap().join("test");
+    let scan = |min_depth: usize, max_depth: Option<usize>| {
+        let mut options = ScannerOptions::new();
+        options.min_depth = min_depth;
+        options.max_depth = max_depth;
+        let mut files = DirectoryScanner::collect(root_dir.clone(), Arc::new(options));
+        files.sort();
+        files.iter().map(|file| file.to_string_lossy().replace(&format!("{}/", root_dir.display()), "")).collect::<Vec<String>>()
+    };
+    assert_eq!(scan(0, Some(1)), vec!["first", "second", "third_dir/"]);
+    assert_eq!(scan(2, None), vec!["third_dir/third"]);
+    assert!(scan(3, None).is_empty());
+}
+
+#[test]
+fn hidden_entries_left_out_are_scanned_on_their_own() {
+    use std::env;
+    use std::fs::File;
+    use std::mem;
+    let root_dir = env::temp_dir().join(format!("fuzzy_scanner_hidden_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root_dir);
+    fs::create_dir_all(root_dir.join("src").join(".cache").join("deep")).unwrap();
+    fs::create_dir_all(root_dir.join(".git")).unwrap();
+    for file in [".env", "src/main.rs", "src/.cache/deep/entry", ".git/HEAD"].iter() {
+        File::create(root_dir.join(file)).unwrap();
+    }
+    let (tx, rx) = mpsc::channel();
+    let hidden = Arc::new(Mutex::new(HiddenEntries::new()));
+    let mut scanner = DirectoryScanner::new(root_dir.clone(), Arc::new(Mutex::new(tx)), Arc::new(ScannerOptions::new()));
+    scanner.record_hidden(hidden.clone());
+    scanner.scan(Arc::new(AtomicUsize::new(0)));
+    drop(scanner);
+    let mut files: Vec<OsString> = rx.iter().flat_map(|files| files.into_iter()).collect();
+    files.sort();
+    let path = |file: &str| root_dir.join(file).into_os_string();
+    assert_eq!(files, vec![path("src/"), path("src/main.rs")]);
+    let hidden = mem::replace(&mut *hidden.lock().unwrap(), HiddenEntries::new());
+    let mut files = hidden.collect();
+    files.sort();
+    assert_eq!(files, vec![path(".env"), path("src/.cache/"), path("src/.cache/deep/"), path("src/.cache/deep/entry")]);
+    let _ = fs::remove_dir_all(&root_dir);
+}
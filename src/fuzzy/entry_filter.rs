@@ -0,0 +1,155 @@
+use std::fs;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use fuzzy::directory_scanner::EntryKind;
+use fuzzy::glob::Glob;
+use fuzzy::options::{EntryFilters, SizeLimit};
+
+// A glob for the path relative to the root when it has a `/`, otherwise for the name.
+struct PathPattern {
+    glob: Glob,
+    anchored: bool,
+}
+
+// `EntryFilters` ready to test entries found below `root_dir` against. Excluded entries are
+// skipped like ignored ones, the other filters only decide what is listed and never stop a
+// directory from being descended into.
+pub struct EntryFilter {
+    root_dir: PathBuf,
+    extensions: Vec<String>,
+    globs: Vec<PathPattern>,
+    excludes: Vec<PathPattern>,
+    size: Option<SizeLimit>,
+    // seconds since the epoch
+    changed_after: Option<i64>,
+    executable: bool,
+}
+
+impl EntryFilter {
+
+    pub fn new(filters: &EntryFilters, root_dir: &Path) -> EntryFilter {
+        let changed_after = filters.changed_within.map(|seconds| now() - seconds as i64);
+        EntryFilter {
+            root_dir: root_dir.to_path_buf(),
+            extensions: filters.extensions.clone(),
+            globs: filters.globs.iter().filter_map(|pattern| path_pattern(pattern)).collect(),
+            excludes: filters.excludes.iter().filter_map(|pattern| path_pattern(pattern)).collect(),
+            size: filters.size,
+            changed_after: changed_after,
+            executable: filters.executable,
+        }
+    }
+
+    // A directory is also tested with a trailing `/`, so `vendor/**` leaves out `vendor` itself.
+    pub fn excludes(&self, path: &Path, kind: EntryKind) -> bool {
+        if self.excludes.is_empty() {
+            return false;
+        }
+        let relative_path = self.relative_path(path);
+        let is_excluded = |relative_path: &str| self.excludes.iter().any(|pattern| pattern.is_match(relative_path));
+        is_excluded(&relative_path) || (kind == EntryKind::Directory && is_excluded(&format!("{}/", relative_path)))
+    }
+
+    // Whether an entry that isn't excluded is listed. Size and permissions only apply to
+    // files, so nothing else is listed when asked for them.
+    pub fn lists(&self, path: &Path, kind: EntryKind, follow_symlinks: bool) -> bool {
+        if !self.extensions.is_empty() {
+            let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase());
+            if !extension.map_or(false, |extension| self.extensions.contains(&extension)) {
+                return false;
+            }
+        }
+        if !self.globs.is_empty() {
+            let relative_path = self.relative_path(path);
+            if !self.globs.iter().any(|pattern| pattern.is_match(&relative_path)) {
+                return false;
+            }
+        }
+        if self.size.is_none() && self.changed_after.is_none() && !self.executable {
+            return true;
+        }
+        if (self.size.is_some() || self.executable) && kind != EntryKind::File {
+            return false;
+        }
+        let metadata = if follow_symlinks { fs::metadata(path) } else { fs::symlink_metadata(path) };
+        let metadata = match metadata {
+            Ok(metadata) => metadata,
+            Err(_) => return false,
+        };
+        let size_matches = match self.size {
+            Some(SizeLimit::AtLeast(size)) => metadata.len() >= size,
+            Some(SizeLimit::AtMost(size)) => metadata.len() <= size,
+            None => true,
+        };
+        let changed_matches = self.changed_after.map_or(true, |changed_after| metadata.mtime() >= changed_after);
+        let executable_matches = !self.executable || metadata.permissions().mode() & 0o111 != 0;
+        size_matches && changed_matches && executable_matches
+    }
+
+    // ------- private methods -------- //
+
+    // patterns only ever match the valid UTF-8 parts of a name, like ignore patterns
+    fn relative_path(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root_dir).unwrap_or(path).to_string_lossy().into_owned()
+    }
+}
+
+impl PathPattern {
+
+    fn is_match(&self, relative_path: &str) -> bool {
+        if self.anchored {
+            self.glob.is_match(relative_path)
+        } else {
+            let name = relative_path.trim_right_matches('/');
+            let name = match name.rfind('/') {
+                Some(index) => &name[(index + 1)..],
+                None => name,
+            };
+            self.glob.is_match(name)
+        }
+    }
+}
+
+// A leading `/` only anchors the pattern, like in ignore files.
+fn path_pattern(pattern: &str) -> Option<PathPattern> {
+    let anchored = pattern.contains('/');
+    Glob::new(pattern.trim_left_matches('/')).ok().map(|glob| PathPattern { glob: glob, anchored: anchored })
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs() as i64).unwrap_or(0)
+}
+
+#[test]
+fn paths_are_matched_by_extension_glob_and_exclude() {
+    let mut filters = EntryFilters::new();
+    filters.extensions = vec![String::from("rs"), String::from("toml")];
+    filters.globs = vec![String::from("**/*_test.rs"), String::from("Cargo.toml")];
+    filters.excludes = vec![String::from("vendor/**"), String::from("target")];
+    let filter = EntryFilter::new(&filters, Path::new("/project"));
+    let lists = |path: &str| filter.lists(Path::new(path), EntryKind::File, false);
+    assert!(lists("/project/src/parser_test.rs"));
+    assert!(lists("/project/parser_test.rs"));
+    assert!(lists("/project/sub/Cargo.toml"));
+    assert!(!lists("/project/src/parser.rs"));
+    assert!(!lists("/project/src/parser_test.py"));
+    assert!(filter.excludes(Path::new("/project/vendor"), EntryKind::Directory));
+    assert!(filter.excludes(Path::new("/project/sub/target"), EntryKind::Directory));
+    assert!(!filter.excludes(Path::new("/project/vendored"), EntryKind::Directory));
+}
+
+#[test]
+fn metadata_filters_only_list_matching_files() {
+    let mut filters = EntryFilters::new();
+    filters.size = Some(SizeLimit::AtMost(0));
+    filters.changed_within = Some(60);
+    let filter = EntryFilter::new(&filters, Path::new("test"));
+    assert!(!filter.lists(Path::new("test/third_dir"), EntryKind::Directory, false));
+    filters.size = Some(SizeLimit::AtLeast(1));
+    assert!(!EntryFilter::new(&filters, Path::new("test")).lists(Path::new("test/first"), EntryKind::File, false));
+    filters.size = None;
+    filters.changed_within = None;
+    filters.executable = true;
+    assert!(!EntryFilter::new(&filters, Path::new("test")).lists(Path::new("test/first"), EntryKind::File, false));
+}
@@ -1,4 +1,5 @@
 use fuzzy::search_phrase::SearchPhrase;
+use fuzzy::result_set::RankedResult;
 use std::sync::{Arc, Mutex, Condvar};
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::mpsc;
@@ -25,7 +26,7 @@ impl SearchPhrases {
 }
 
 pub struct FileFinderEvents {
-    data: Vec<Vec<String>>
+    data: Vec<Vec<RankedResult>>
 }
 
 impl FileFinderEvents {
@@ -34,11 +35,11 @@ impl FileFinderEvents {
         FileFinderEvents { data: vec![] }
     }
 
-    pub fn push(&mut self, data: Vec<String>) {
+    pub fn push(&mut self, data: Vec<RankedResult>) {
         self.data.push(data);
     }
 
-    pub fn export(&mut self) -> Vec<Vec<String>> {
+    pub fn export(&mut self) -> Vec<Vec<RankedResult>> {
         let data = self.data.clone();
         self.data.clear();
         data
@@ -73,7 +74,7 @@ impl EventService {
         self.condvar.notify_all();
     }
 
-    pub fn trigger_file_finder_event(&self, results: Vec<String>) {
+    pub fn trigger_file_finder_event(&self, results: Vec<RankedResult>) {
         self.file_finder_events.lock().unwrap().push(results);
         self.file_finder_condvar.notify_all();
     }
@@ -1,81 +1,142 @@
 extern crate regex;
 
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::AtomicUsize;
 use fuzzy::terminal::Terminal;
-use fuzzy::result_set::ResultSet;
+use fuzzy::result_set::{ResultSet, ScanRoot};
 use fuzzy::event_service::EventService;
-use fuzzy::directory_scanner::DirectoryScanner;
+use fuzzy::directory_scanner::{self, DirectoryScanner, HiddenEntries};
+use fuzzy::daemon::{self, Subscription};
+use fuzzy::cache::{CacheMode, IndexCache};
+use fuzzy::watcher::{Change, TreeWatcher};
+use fuzzy::options::ScannerOptions;
+use fuzzy::search_phrase::SearchPhrase;
 use std::ops::Drop;
-use std::thread;
-use std::sync::mpsc::{Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::sync::mpsc::{Receiver, Sender, RecvTimeoutError};
 use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use fuzzy::stdin_reader::StdinReader;
 
 pub struct FileFinder {
-    pub terminal: Arc<Terminal>,
-    event_service: Arc<EventService>,
-    result_set: Arc<Mutex<ResultSet>>,
+    root_scanner: RootScanner,
     tx: Sender<usize>,
     subscriber_channels: Vec<Arc<Mutex<Sender<Vec<String>>>>>,
-    root_dir: PathBuf
+}
+
+// Scans one root into the shared result set. Cheap to clone, so each root gets a thread.
+#[derive(Clone)]
+struct RootScanner {
+    terminal: Arc<Terminal>,
+    event_service: Arc<EventService>,
+    result_set: Arc<Mutex<ResultSet>>,
+    scanner_options: Arc<ScannerOptions>,
+    use_daemon: bool,
+    cache: CacheMode
+}
+
+// What showing the hidden files of a root for the first time needs: its watcher, or the
+// changes the daemon sends, and where the hidden files come from, known once the root was
+// scanned.
+struct HiddenFiles {
+    requested: bool,
+    source: Option<HiddenSource>,
+    watcher: Option<TreeWatcher>,
+    subscription: Option<Subscription>,
+}
+
+enum HiddenSource {
+    // what the scan left out
+    Recorded(HiddenEntries),
+    // the daemon or the cache listed the other files, so they are asked again with hidden files
+    Daemon,
+    Cache,
 }
 
 impl FileFinder {
 
-    pub fn new(terminal: Arc<Terminal>, event_service: Arc<EventService>) -> Arc<Mutex<FileFinder>> {
+    pub fn new(terminal: Arc<Terminal>, event_service: Arc<EventService>, scanner_options: ScannerOptions, use_daemon: bool, cache: CacheMode) -> Arc<Mutex<FileFinder>> {
         let (tx, _) = mpsc::channel();
         Arc::new(Mutex::new(
             FileFinder { 
-                terminal: terminal,
-                event_service: event_service,
-                result_set: Arc::new(Mutex::new(ResultSet::new())),
+                root_scanner: RootScanner {
+                    terminal: terminal,
+                    event_service: event_service,
+                    result_set: Arc::new(Mutex::new(ResultSet::new())),
+                    scanner_options: Arc::new(scanner_options),
+                    use_daemon: use_daemon,
+                    cache: cache
+                },
                 tx: tx,
                 subscriber_channels: vec![],
-                root_dir: PathBuf::new()
             }
         ))
     }
 
+    pub fn set_boosts(&self, boosts: HashMap<String, i32>) {
+        self.root_scanner.result_set.lock().unwrap().set_boosts(boosts);
+    }
+
     pub fn add_subscriber_channel(&mut self, subscriber_channel: Arc<Mutex<Sender<Vec<String>>>>) {
         self.subscriber_channels.push(subscriber_channel);
     }
 
-    pub fn start(&mut self, root_dir: &PathBuf) {
-        self.root_dir = root_dir.clone();
-        self.listen_for_filters();
+    // Roots are scanned concurrently, and this returns once they all have been.
+    pub fn start(&mut self, root_dirs: &Vec<PathBuf>) {
+        let roots: Vec<(ScanRoot, Arc<Mutex<HiddenFiles>>)> = scan_roots(root_dirs).into_iter().map(|root| {
+            (root, Arc::new(Mutex::new(HiddenFiles { requested: false, source: None, watcher: None, subscription: None })))
+        }).collect();
+        self.listen_for_filters(roots.clone());
+        let scans: Vec<JoinHandle<()>> = roots.into_iter().map(|(root, hidden)| {
+            let root_scanner = self.root_scanner.clone();
+            thread::spawn(move || root_scanner.scan(root, hidden))
+        }).collect();
+        for scan in scans {
+            let _ = scan.join();
+        }
+        self.root_scanner.update_subscribers();
+    }
+
+    pub fn start_from_stdin(&mut self) {
+        self.listen_for_filters(vec![]);
         let (tx, rx) = mpsc::channel();
-        let mut scanner = DirectoryScanner::new(root_dir.clone(), Arc::new(Mutex::new(tx)));
+        let reader = StdinReader::new(Arc::new(Mutex::new(tx)));
         thread::spawn(move || {
-            scanner.scan(Arc::new(AtomicUsize::new(0)));
-            // what checks this thread and make sure it's killed properly
+            reader.read();
         });
-        self.listen_for_scanner_updates(rx);
-        self.update_subscribers();
+        self.root_scanner.listen_for_scanner_updates(rx, &ScanRoot::none());
+        self.root_scanner.update_subscribers();
     }
 
     // ----------- private methods ---------- //
 
-    fn listen_for_scanner_updates(&self, receiver: Receiver<Vec<String>>) {
-        for results in receiver.iter() {
-            let mut result_set = self.result_set.lock().unwrap();
-            result_set.add_many(results, self.root_dir.to_str().unwrap());
-            if result_set.number_of_results() < 100 {
-                self.event_service.trigger_file_finder_event(result_set.to_vec());
-            } else {
-                // update count here?
+    // The hidden files of `roots` are added once they are first asked for.
+    fn listen_for_filters(&self, roots: Vec<(ScanRoot, Arc<Mutex<HiddenFiles>>)>) {
+        let root_scanner = self.root_scanner.clone();
+        let mut hidden_requested = root_scanner.scanner_options.include_hidden;
+        let mut apply = move |search_phrase: &SearchPhrase| {
+            if search_phrase.include_hidden && !hidden_requested {
+                hidden_requested = true;
+                for &(ref root, ref hidden) in roots.iter() {
+                    root_scanner.request_hidden(root, hidden);
+                }
             }
+            apply_search_phrase(search_phrase, &root_scanner.result_set, &root_scanner.event_service, &root_scanner.terminal);
+        };
+        let event_service = self.root_scanner.event_service.clone();
+        // a search phrase given up front is waiting before anything was listening for it
+        let initial_search_phrases = event_service.search_phrases.lock().unwrap().export();
+        match initial_search_phrases.last() {
+            Some(search_phrase) => apply(search_phrase),
+            None => {}
         }
-    }
-
-    fn update_subscribers(&self) {
-        let result_set = self.result_set.lock().unwrap();
-        self.event_service.trigger_file_finder_event(result_set.to_vec());
-    }
-
-    fn listen_for_filters(&self) {
-        let event_service = self.event_service.clone();
-        let result_set = self.result_set.clone();
         thread::spawn(move|| {
             loop {
 
@@ -86,9 +147,7 @@ impl FileFinder {
                 let events = search_phrases.export();
                 if events.len() > 0 {
                     let last_event = events.last().unwrap();
-                    let mut locked_result_set = result_set.lock().unwrap();
-                    let filtered_results = locked_result_set.apply_filter(last_event.to_regex());
-                    event_service.trigger_file_finder_event(filtered_results.clone());
+                    apply(last_event);
                 } else {
                     break;
                 }
@@ -97,6 +156,360 @@ impl FileFinder {
     }
 }
 
+impl RootScanner {
+
+    // A running daemon has the files ready, then the cache does, otherwise the root is scanned.
+    // The root is watched here unless the daemon sends the changes to what it listed.
+    fn scan(&self, root: ScanRoot, hidden: Arc<Mutex<HiddenFiles>>) {
+        if self.terminal.is_finished() {
+            return;
+        }
+        let (tx, rx) = mpsc::channel();
+        let daemon_files = if self.use_daemon { daemon::subscribe(&daemon::socket_path(), &root.root_dir, &self.scanner_options) } else { None };
+        let recorded = Arc::new(Mutex::new(HiddenEntries::new()));
+        let mut subscription = None;
+        let source = match daemon_files {
+            Some((files, daemon_subscription)) => {
+                subscription = daemon_subscription;
+                if subscription.is_none() {
+                    self.watch(root.clone(), hidden.clone());
+                }
+                let _ = tx.send(files);
+                drop(tx);
+                HiddenSource::Daemon
+            }
+            None if self.cache != CacheMode::Off => {
+                drop(tx);
+                self.watch(root.clone(), hidden.clone());
+                self.scan_with_cache(root.clone());
+                HiddenSource::Cache
+            }
+            None => {
+                self.watch(root.clone(), hidden.clone());
+                let mut scanner = DirectoryScanner::new(root.root_dir.clone(), Arc::new(Mutex::new(tx)), self.scanner_options.clone());
+                scanner.record_hidden(recorded.clone());
+                scanner.record_links(self.result_set.lock().unwrap().links());
+                thread::spawn(move || {
+                    scanner.scan(Arc::new(AtomicUsize::new(0)));
+                    // what checks this thread and make sure it's killed properly
+                });
+                HiddenSource::Recorded(HiddenEntries::new())
+            }
+        };
+        self.listen_for_scanner_updates(rx, &root);
+        // the changes are to what the daemon listed, so they only apply once that was added
+        if let Some(subscription) = subscription {
+            self.follow_daemon(root.clone(), subscription, hidden.clone());
+        }
+        let source = match source {
+            // every scanner is done once the channel is closed
+            HiddenSource::Recorded(_) => HiddenSource::Recorded(mem::replace(&mut *recorded.lock().unwrap(), HiddenEntries::new())),
+            source => source,
+        };
+        let mut hidden_files = hidden.lock().unwrap();
+        if hidden_files.requested {
+            self.add_hidden(root, source, hidden.clone());
+        } else {
+            hidden_files.source = Some(source);
+        }
+    }
+
+    // Hidden directories are watched from now on, and once the root was scanned what the
+    // scan left out is added.
+    fn request_hidden(&self, root: &ScanRoot, hidden: &Arc<Mutex<HiddenFiles>>) {
+        let (root_scanner, root, hidden) = (self.clone(), root.clone(), hidden.clone());
+        thread::spawn(move || {
+            let mut hidden_files = hidden.lock().unwrap();
+            hidden_files.requested = true;
+            if let Some(ref watcher) = hidden_files.watcher {
+                if let Err(error) = watcher.watch_hidden() {
+                    stop_watching(&root_scanner.terminal, error);
+                }
+            }
+            if let Some(source) = hidden_files.source.take() {
+                root_scanner.add_hidden(root, source, hidden.clone());
+            }
+        });
+    }
+
+    // Files listed by the daemon are asked for again with the hidden ones, and the changes
+    // to those replace the changes it sent so far.
+    fn add_hidden(&self, root: ScanRoot, source: HiddenSource, hidden: Arc<Mutex<HiddenFiles>>) {
+        let root_scanner = self.clone();
+        thread::spawn(move || {
+            if root_scanner.terminal.is_finished() {
+                return;
+            }
+            let options = including_hidden(&root_scanner.scanner_options);
+            let mut subscription = None;
+            let files = match source {
+                HiddenSource::Recorded(entries) => entries.collect(),
+                HiddenSource::Daemon => {
+                    match daemon::subscribe(&daemon::socket_path(), &root.root_dir, &options) {
+                        Some((files, daemon_subscription)) => {
+                            subscription = daemon_subscription;
+                            files
+                        }
+                        None => DirectoryScanner::collect(root.root_dir.clone(), options.clone()),
+                    }
+                }
+                HiddenSource::Cache => {
+                    let mut cache = IndexCache::load(IndexCache::default_dir(), &root.root_dir, options);
+                    if root_scanner.cache == CacheMode::Rebuild {
+                        cache.clear();
+                    }
+                    cache.revalidate(None);
+                    let _ = cache.save();
+                    cache.files()
+                }
+            };
+            {
+                let mut result_set = root_scanner.result_set.lock().unwrap();
+                result_set.add_hidden(files, &root);
+                root_scanner.event_service.trigger_file_finder_event(result_set.to_ranked_vec());
+            }
+            match subscription {
+                Some(subscription) => root_scanner.follow_daemon(root, subscription, hidden),
+                None => {
+                    // without changes to the hidden files from the daemon they are watched here
+                    let mut hidden_files = hidden.lock().unwrap();
+                    if let Some(previous) = hidden_files.subscription.take() {
+                        previous.close();
+                        drop(hidden_files);
+                        root_scanner.watch(root, hidden.clone());
+                    }
+                }
+            }
+        });
+    }
+
+    // Keeps the results below a root current while fuzzy is open. Watching starts before
+    // the scan, so files created meanwhile can be reported twice and are only added once.
+    fn watch(&self, root: ScanRoot, hidden: Arc<Mutex<HiddenFiles>>) {
+        let root_scanner = self.clone();
+        thread::spawn(move || {
+            let (options, result_set, event_service, terminal) = (&root_scanner.scanner_options, &root_scanner.result_set, &root_scanner.event_service, &root_scanner.terminal);
+            let watcher = match TreeWatcher::new(&root.root_dir, options.clone()) {
+                Ok(watcher) => watcher,
+                Err(error) => return stop_watching(terminal, error),
+            };
+            watcher.record_links(result_set.lock().unwrap().links());
+            {
+                let mut hidden = hidden.lock().unwrap();
+                if hidden.requested {
+                    if let Err(error) = watcher.watch_hidden() {
+                        return stop_watching(terminal, error);
+                    }
+                }
+                hidden.watcher = Some(watcher.clone());
+            }
+            loop {
+                let changes = match watcher.wait() {
+                    Ok(changes) => changes,
+                    Err(error) => return stop_watching(terminal, error),
+                };
+                if terminal.is_finished() {
+                    break;
+                }
+                if changes.contains(&Change::Rescan) {
+                    root_scanner.rescan(&root, &hidden);
+                    continue;
+                }
+                let mut result_set = result_set.lock().unwrap();
+                apply_changes(&mut result_set, &root, changes);
+                event_service.trigger_file_finder_event(result_set.to_ranked_vec());
+            }
+        });
+    }
+
+    // Keeps the results below a root current with the changes the daemon sends to what it
+    // listed. Once it stops sending them the root is watched here instead. A subscription
+    // this replaces is closed.
+    fn follow_daemon(&self, root: ScanRoot, mut subscription: Subscription, hidden: Arc<Mutex<HiddenFiles>>) {
+        {
+            let mut hidden_files = hidden.lock().unwrap();
+            if let Some(previous) = hidden_files.subscription.take() {
+                previous.close();
+            }
+            hidden_files.subscription = subscription.try_clone().ok();
+        }
+        let root_scanner = self.clone();
+        thread::spawn(move || {
+            loop {
+                let changes = match subscription.wait() {
+                    Ok(changes) => changes,
+                    Err(_) if subscription.is_closed() => return,
+                    // the daemon is gone
+                    Err(_) => vec![Change::Rescan],
+                };
+                if root_scanner.terminal.is_finished() {
+                    break;
+                }
+                if changes.contains(&Change::Rescan) {
+                    // replacing a subscription closes it with `hidden` locked
+                    let mut hidden_files = hidden.lock().unwrap();
+                    if !subscription.is_closed() {
+                        if let Some(current) = hidden_files.subscription.take() {
+                            current.close();
+                        }
+                        drop(hidden_files);
+                        root_scanner.watch(root.clone(), hidden.clone());
+                        root_scanner.rescan(&root, &hidden);
+                    }
+                    break;
+                }
+                let mut result_set = root_scanner.result_set.lock().unwrap();
+                record_links(&result_set, &changes);
+                apply_changes(&mut result_set, &root, changes);
+                root_scanner.event_service.trigger_file_finder_event(result_set.to_ranked_vec());
+            }
+        });
+    }
+
+    // Replaces the results below `root` with what a new scan finds.
+    fn rescan(&self, root: &ScanRoot, hidden: &Arc<Mutex<HiddenFiles>>) {
+        let options = if hidden.lock().unwrap().requested { including_hidden(&self.scanner_options) } else { self.scanner_options.clone() };
+        let files = DirectoryScanner::collect(root.root_dir.clone(), options);
+        let mut result_set = self.result_set.lock().unwrap();
+        result_set.remove(root.root_dir.as_os_str(), root);
+        result_set.add_many(files, root);
+        self.event_service.trigger_file_finder_event(result_set.to_ranked_vec());
+    }
+
+    // Shows the cached files at once, then corrects them as the cache is revalidated.
+    fn scan_with_cache(&self, root: ScanRoot) {
+        let mut cache = IndexCache::load(IndexCache::default_dir(), &root.root_dir, self.scanner_options.clone());
+        if self.cache == CacheMode::Rebuild {
+            cache.clear();
+        }
+        if !cache.is_empty() {
+            let mut result_set = self.result_set.lock().unwrap();
+            result_set.add_many(cache.files(), &root);
+            self.event_service.trigger_file_finder_event(result_set.to_ranked_vec());
+        }
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            cache.revalidate(Some(&tx));
+            let _ = cache.save();
+        });
+        self.listen_for_updates(rx, |result_set, changes| apply_changes(result_set, &root, changes));
+    }
+
+    fn listen_for_scanner_updates(&self, receiver: Receiver<Vec<OsString>>, root: &ScanRoot) {
+        self.listen_for_updates(receiver, |result_set, results| result_set.add_many(results, root));
+    }
+
+    // Stops early once the user is done, a scan or stream may never get to the end.
+    fn listen_for_updates<T, F: Fn(&mut ResultSet, T)>(&self, receiver: Receiver<T>, apply: F) {
+        let mut last_update = Instant::now();
+        let mut pending_update = false;
+        loop {
+            match receiver.recv_timeout(Duration::from_millis(100)) {
+                Ok(results) => {
+                    let mut result_set = self.result_set.lock().unwrap();
+                    apply(&mut result_set, results);
+                    pending_update = true;
+                    if result_set.number_of_results() < 100 || last_update.elapsed() > Duration::from_millis(100) {
+                        self.event_service.trigger_file_finder_event(result_set.to_ranked_vec());
+                        last_update = Instant::now();
+                        pending_update = false;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if self.terminal.is_finished() {
+                        break;
+                    }
+                    if pending_update {
+                        self.update_subscribers();
+                        last_update = Instant::now();
+                        pending_update = false;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+
+    fn update_subscribers(&self) {
+        let result_set = self.result_set.lock().unwrap();
+        self.event_service.trigger_file_finder_event(result_set.to_ranked_vec());
+    }
+}
+
+// Without root directories the current directory is scanned and results are shown relative
+// to it. Otherwise results are labelled with the name of the root they were found under, or
+// the root as it was given when names are shared, and printed under the root as it was given.
+pub fn scan_roots(root_dirs: &Vec<PathBuf>) -> Vec<ScanRoot> {
+    if root_dirs.is_empty() {
+        return vec![ScanRoot::new(env::current_dir().unwrap(), String::new(), PathBuf::new())];
+    }
+    let roots: Vec<ScanRoot> = root_dirs.iter().map(|root_dir| {
+        let absolute_root_dir = fs::canonicalize(root_dir).unwrap_or(root_dir.clone());
+        let label = root_dir.file_name().or(absolute_root_dir.file_name())
+            .map_or_else(|| root_dir.to_string_lossy().into_owned(), |name| name.to_string_lossy().into_owned());
+        ScanRoot::new(absolute_root_dir, label, root_dir.clone())
+    }).collect();
+    roots.iter().map(|root| {
+        if roots.iter().filter(|other| other.label == root.label).count() > 1 {
+            ScanRoot { label: root.output_root.to_string_lossy().into_owned(), ..root.clone() }
+        } else {
+            root.clone()
+        }
+    }).collect()
+}
+
+// The results stay as they are from then on, which the user is told about. Other systems
+// than Linux never watch, so there is nothing to tell.
+fn stop_watching(terminal: &Arc<Terminal>, error: io::Error) {
+    if cfg!(target_os = "linux") && !terminal.is_finished() {
+        terminal.show_warning(format!("not watching for changes: {}", error));
+    }
+}
+
+// `Rescan` is left to the caller.
+fn apply_changes(result_set: &mut ResultSet, root: &ScanRoot, changes: Vec<Change>) {
+    for change in changes {
+        match change {
+            Change::Added(files) => result_set.add_many(files, root),
+            Change::Removed(path) => result_set.remove(&path, root),
+            Change::Rescan => {}
+        }
+    }
+}
+
+// What a watcher records while it runs here, for files the daemon says were added.
+fn record_links(result_set: &ResultSet, changes: &Vec<Change>) {
+    let links = result_set.links();
+    let mut links = links.lock().unwrap();
+    for change in changes.iter() {
+        if let Change::Added(ref files) = *change {
+            // directories are sent with a trailing `/`
+            links.extend(files.iter().filter_map(|file| directory_scanner::link(&Path::new(file).components().collect())));
+        }
+    }
+}
+
+fn including_hidden(options: &Arc<ScannerOptions>) -> Arc<ScannerOptions> {
+    let mut options = (**options).clone();
+    options.include_hidden = true;
+    Arc::new(options)
+}
+
+fn apply_search_phrase(search_phrase: &SearchPhrase, result_set: &Arc<Mutex<ResultSet>>, event_service: &Arc<EventService>, terminal: &Arc<Terminal>) {
+    match search_phrase.to_matcher() {
+        Ok(matcher) => {
+            terminal.show_filter_error(None);
+            let mut locked_result_set = result_set.lock().unwrap();
+            locked_result_set.set_show_hidden(search_phrase.include_hidden);
+            let filtered_results = locked_result_set.apply_filter(matcher);
+            event_service.trigger_file_finder_event(filtered_results);
+        }
+        Err(error) => {
+            terminal.show_filter_error(Some(error));
+        }
+    }
+}
+
 impl Drop for FileFinder {
 
     fn drop(&mut self) {
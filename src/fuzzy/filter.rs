@@ -0,0 +1,137 @@
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use fuzzy::cache::{CacheMode, IndexCache};
+use fuzzy::daemon;
+use fuzzy::directory_scanner::DirectoryScanner;
+use fuzzy::event_service::EventService;
+use fuzzy::file_finder;
+use fuzzy::frecency::FrecencyDatabase;
+use fuzzy::options::Options;
+use fuzzy::result_set::{ResultSet, ScanRoot};
+use fuzzy::search_phrase::SearchPhrase;
+use fuzzy::stdin_reader::StdinReader;
+
+// Ranks every candidate the way the interactive finder does, without touching the terminal.
+pub fn find_matches(options: &Options, query: &str, frecency: &FrecencyDatabase) -> Result<Vec<OsString>, String> {
+    let mut search_phrase = SearchPhrase::from_string(query.to_string(), Arc::new(EventService::new()));
+    search_phrase.exact = options.exact;
+    search_phrase.regex_mode = options.regex_mode;
+    search_phrase.case = options.case;
+    let matcher = try!(search_phrase.to_matcher());
+    let mut result_set = ResultSet::new();
+    if options.read_stdin {
+        let (tx, rx) = mpsc::channel();
+        let reader = StdinReader::new(Arc::new(Mutex::new(tx)));
+        thread::spawn(move || {
+            reader.read();
+        });
+        for results in rx.iter() {
+            result_set.add_many(results, &ScanRoot::none());
+        }
+    } else {
+        let roots = file_finder::scan_roots(&options.root_dirs);
+        result_set.set_boosts(frecency.boosts(&roots, FrecencyDatabase::now()));
+        // every root is listed at once, and added in the order given so the first root
+        // a file is below is the one it is listed under
+        let listings: Vec<(ScanRoot, JoinHandle<Vec<OsString>>)> = roots.into_iter().map(|root| {
+            let (options, root_dir) = (options.clone(), root.root_dir.clone());
+            (root, thread::spawn(move || list_files(&options, root_dir)))
+        }).collect();
+        for (root, listing) in listings {
+            result_set.add_many(listing.join().unwrap_or(vec![]), &root);
+        }
+    }
+    Ok(result_set.apply_filter(matcher).into_iter().map(|result| result.output_path()).collect())
+}
+
+// From the daemon, then the cache, otherwise by scanning.
+fn list_files(options: &Options, root_dir: PathBuf) -> Vec<OsString> {
+    if options.use_daemon {
+        if let Some(files) = daemon::request_files(&daemon::socket_path(), &root_dir, &options.scanner) {
+            return files;
+        }
+    }
+    if options.cache != CacheMode::Off {
+        return cached_files(options, &root_dir);
+    }
+    DirectoryScanner::collect(root_dir, Arc::new(options.scanner.clone()))
+}
+
+// The cached files, after bringing the cache up to date.
+fn cached_files(options: &Options, root_dir: &PathBuf) -> Vec<OsString> {
+    let mut cache = IndexCache::load(IndexCache::default_dir(), root_dir, Arc::new(options.scanner.clone()));
+    if options.cache == CacheMode::Rebuild {
+        cache.clear();
+    }
+    cache.revalidate(None);
+    if let Err(error) = cache.save() {
+        let _ = writeln!(io::stderr(), "fuzzy: could not save the cache: {}", error);
+    }
+    cache.files()
+}
+
+// One result per line, or NUL terminated with `print0` for paths containing newlines.
+// Paths are written byte for byte, whatever their encoding.
+pub fn write_results<W: Write>(out: &mut W, results: &Vec<OsString>, print0: bool) -> io::Result<()> {
+    let terminator = if print0 { b"\0" } else { b"\n" };
+    for result in results.iter() {
+        try!(out.write_all(result.as_bytes()));
+        try!(out.write_all(terminator));
+    }
+    Ok(())
+}
+
+// Prints the ranked matches and returns the exit code.
+pub fn run(options: &Options, query: &str) -> i32 {
+    let frecency = FrecencyDatabase::load(FrecencyDatabase::default_path());
+    match find_matches(options, query, &frecency) {
+        Ok(matches) => {
+            let stdout = io::stdout();
+            let _ = write_results(&mut stdout.lock(), &matches, options.print0);
+            if matches.is_empty() { 1 } else { 0 }
+        }
+        Err(error) => {
+            let _ = writeln!(io::stderr(), "fuzzy: {}", error);
+            2
+        }
+    }
+}
+
+#[test]
+fn matches_are_found_and_ranked_without_a_terminal() {
+    use fuzzy::options::EntryTypes;
+    let mut options = Options::new();
+    options.root_dirs = vec![PathBuf::from("test")];
+    options.cache = CacheMode::Off;
+    options.scanner.types = EntryTypes { files: true, directories: false, symlinks: false };
+    let frecency = FrecencyDatabase::load(None);
+    assert_eq!(find_matches(&options, "thi", &frecency), Ok(vec![OsString::from("test/third_dir/third")]));
+    assert_eq!(find_matches(&options, "t", &frecency), Ok(vec![OsString::from("test/third_dir/third"), OsString::from("test/first"), OsString::from("test/second")]));
+    assert_eq!(find_matches(&options, "zzz", &frecency), Ok(vec![]));
+    options.scanner.types = EntryTypes { files: false, directories: true, symlinks: false };
+    assert_eq!(find_matches(&options, "t", &frecency), Ok(vec![OsString::from("test/third_dir/")]));
+}
+
+#[test]
+fn invalid_regex_queries_are_reported() {
+    let mut options = Options::new();
+    options.regex_mode = true;
+    assert!(find_matches(&options, "(", &FrecencyDatabase::load(None)).is_err());
+}
+
+#[test]
+fn results_are_written_newline_or_nul_terminated() {
+    use std::os::unix::ffi::OsStringExt;
+    let results = vec![OsString::from("first"), OsString::from_vec(b"caf\xe9".to_vec())];
+    let mut out = vec![];
+    write_results(&mut out, &results, false).unwrap();
+    assert_eq!(out, b"first\ncaf\xe9\n".to_vec());
+    out.clear();
+    write_results(&mut out, &results, true).unwrap();
+    assert_eq!(out, b"first\0caf\xe9\0".to_vec());
+}
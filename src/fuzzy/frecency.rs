@@ -0,0 +1,255 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use fuzzy::data_file;
+use fuzzy::result_set::ScanRoot;
+
+const MAX_ENTRIES: usize = 2000;
+const MAX_BOOST: i32 = 12;
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+const MAX_AGE: u64 = 90 * DAY;
+
+struct Entry {
+    root: OsString,
+    path: OsString,
+    count: u32,
+    last_used: u64,
+}
+
+impl Entry {
+
+    // Frequency, weighted down as the last use gets older.
+    fn frecency(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last_used);
+        let weight = if age < HOUR {
+            4.0
+        } else if age < DAY {
+            2.0
+        } else if age < WEEK {
+            0.5
+        } else {
+            0.25
+        };
+        self.count as f64 * weight
+    }
+}
+
+// Which results were picked, per root directory, so they can be ranked higher next time.
+// Stored as `root<TAB>path<TAB>count<TAB>last used` lines, `path` relative to `root`.
+// Backslashes, tabs and newlines in `root` and `path` are escaped as `\\`, `\t` and `\n`.
+pub struct FrecencyDatabase {
+    path: Option<PathBuf>,
+    entries: Vec<Entry>,
+}
+
+impl FrecencyDatabase {
+
+    // A database that can't be read starts out empty.
+    pub fn load(path: Option<PathBuf>) -> FrecencyDatabase {
+        let entries = data_file::read(&path).split(|byte| *byte == b'\n').filter_map(parse_entry).collect();
+        FrecencyDatabase { path: path, entries: entries }
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        data_file::default_path("frecency")
+    }
+
+    pub fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
+    }
+
+    // `roots` are the roots the results were found under, `result` a path exactly as it was printed.
+    pub fn record(&mut self, roots: &Vec<ScanRoot>, result: &OsStr, now: u64) {
+        let (root, path) = match locate(roots, result) {
+            Some(location) => location,
+            None => return,
+        };
+        match self.entries.iter().position(|entry| entry.root == root && entry.path == path) {
+            Some(index) => {
+                self.entries[index].count += 1;
+                self.entries[index].last_used = now;
+            }
+            None => self.entries.push(Entry { root: root, path: path, count: 1, last_used: now }),
+        }
+    }
+
+    // The score to add to each result, keyed by how it is displayed.
+    pub fn boosts(&self, roots: &Vec<ScanRoot>, now: u64) -> HashMap<String, i32> {
+        let mut boosts = HashMap::new();
+        for scan_root in roots.iter() {
+            for entry in self.entries.iter().filter(|entry| entry.root == scan_root.root_dir.as_os_str()) {
+                let boost = capped(entry.frecency(now) * 2.0, MAX_BOOST);
+                if boost > 0 {
+                    boosts.insert(under_prefix(&scan_root.label, &entry.path.to_string_lossy()), boost);
+                }
+            }
+        }
+        boosts
+    }
+
+    // Forgets files that are gone or haven't been picked in a long time.
+    pub fn prune(&mut self, now: u64) {
+        self.entries.retain(|entry| {
+            now.saturating_sub(entry.last_used) < MAX_AGE && Path::new(&entry.root).join(&entry.path).exists()
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn save(&mut self, now: u64) -> io::Result<()> {
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.sort_by(|a, b| b.frecency(now).partial_cmp(&a.frecency(now)).unwrap_or(Ordering::Equal));
+            self.entries.truncate(MAX_ENTRIES);
+        }
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let mut content = vec![];
+        for entry in self.entries.iter() {
+            content.extend(escape(&entry.root));
+            content.push(b'\t');
+            content.extend(escape(&entry.path));
+            content.extend(format!("\t{}\t{}\n", entry.count, entry.last_used).into_bytes());
+        }
+        data_file::write(path, &content)
+    }
+}
+
+fn parse_entry(line: &[u8]) -> Option<Entry> {
+    let fields: Vec<&[u8]> = line.split(|byte| *byte == b'\t').collect();
+    if fields.len() != 4 {
+        return None;
+    }
+    let (count, last_used) = (String::from_utf8_lossy(fields[2]), String::from_utf8_lossy(fields[3]));
+    match (unescape(fields[0]), unescape(fields[1]), count.parse(), last_used.parse()) {
+        (Some(root), Some(path), Ok(count), Ok(last_used)) => Some(Entry { root: root, path: path, count: count, last_used: last_used }),
+        _ => None,
+    }
+}
+
+fn escape(value: &OsStr) -> Vec<u8> {
+    let mut escaped = vec![];
+    for byte in value.as_bytes().iter() {
+        match *byte {
+            b'\\' => escaped.extend(b"\\\\"),
+            b'\t' => escaped.extend(b"\\t"),
+            b'\n' => escaped.extend(b"\\n"),
+            byte => escaped.push(byte),
+        }
+    }
+    escaped
+}
+
+fn unescape(field: &[u8]) -> Option<OsString> {
+    let mut value = vec![];
+    let mut bytes = field.iter();
+    while let Some(byte) = bytes.next() {
+        if *byte != b'\\' {
+            value.push(*byte);
+            continue;
+        }
+        match bytes.next() {
+            Some(&b'\\') => value.push(b'\\'),
+            Some(&b't') => value.push(b'\t'),
+            Some(&b'n') => value.push(b'\n'),
+            _ => return None,
+        }
+    }
+    Some(OsStr::from_bytes(&value).to_os_string())
+}
+
+// The root a printed result belongs to, and its path relative to that root.
+fn locate(roots: &Vec<ScanRoot>, result: &OsStr) -> Option<(OsString, OsString)> {
+    for scan_root in roots.iter() {
+        let root = scan_root.root_dir.as_os_str().to_os_string();
+        let output_root = scan_root.output_root.as_os_str().as_bytes();
+        if output_root.is_empty() {
+            return Some((root, result.to_os_string()));
+        }
+        let length = output_root.iter().rposition(|byte| *byte != b'/').map_or(0, |index| index + 1);
+        let result = result.as_bytes();
+        if result.starts_with(&output_root[..length]) && result[length..].starts_with(b"/") {
+            return Some((root, OsStr::from_bytes(&result[(length + 1)..]).to_os_string()));
+        }
+    }
+    None
+}
+
+// The same prefixing `ResultSet::add_many` does, for labels.
+fn under_prefix(prefix: &str, path: &str) -> String {
+    if prefix.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}/{}", prefix.trim_right_matches('/'), path)
+    }
+}
+
+fn capped(value: f64, max: i32) -> i32 {
+    if value >= max as f64 { max } else { value as i32 }
+}
+
+#[test]
+fn recent_and_frequent_picks_get_the_biggest_boost() {
+    let roots = vec![ScanRoot::new(PathBuf::from("/projects/fuzzy"), String::from("fuzzy"), PathBuf::from("../fuzzy/"))];
+    let mut database = FrecencyDatabase::load(None);
+    let now = 10 * WEEK;
+    database.record(&roots, OsStr::new("../fuzzy/src/lib.rs"), now - 2 * WEEK);
+    database.record(&roots, OsStr::new("../fuzzy/src/main.rs"), now - 2 * DAY);
+    database.record(&roots, OsStr::new("../fuzzy/src/main.rs"), now - DAY);
+    database.record(&roots, OsStr::new("../fuzzy/README.md"), now);
+    database.record(&roots, OsStr::new("fuzzy/README.md"), now);
+    let boosts = database.boosts(&roots, now);
+    assert_eq!(boosts.get("fuzzy/README.md"), Some(&8));
+    assert_eq!(boosts.get("fuzzy/src/main.rs"), Some(&2));
+    assert_eq!(boosts.get("fuzzy/src/lib.rs"), None);
+    assert_eq!(boosts.len(), 2);
+}
+
+#[test]
+fn the_database_is_saved_and_pruned() {
+    use std::env;
+    use std::fs;
+    let path = env::temp_dir().join(format!("fuzzy_frecency_test_{}", std::process::id()));
+    let roots = vec![ScanRoot::new(env::current_dir().unwrap(), String::new(), PathBuf::new())];
+    let now = FrecencyDatabase::now();
+    let mut database = FrecencyDatabase::load(Some(path.clone()));
+    database.clear();
+    database.record(&roots, OsStr::new("test/first"), now);
+    database.record(&roots, OsStr::new("test/gone"), now);
+    database.record(&roots, OsStr::new("test/second"), now - MAX_AGE);
+    database.save(now).unwrap();
+
+    let mut database = FrecencyDatabase::load(Some(path.clone()));
+    assert_eq!(database.boosts(&roots, now).len(), 2);
+    database.prune(now);
+    assert_eq!(database.boosts(&roots, now).keys().collect::<Vec<&String>>(), vec!["test/first"]);
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn paths_are_stored_exactly_whatever_bytes_they_hold() {
+    use std::env;
+    use std::fs;
+    let path = env::temp_dir().join(format!("fuzzy_frecency_bytes_test_{}", std::process::id()));
+    let roots = vec![ScanRoot::new(PathBuf::from("/root\twith\\tab"), String::from("root"), PathBuf::from("root"))];
+    let now = FrecencyDatabase::now();
+    let mut database = FrecencyDatabase::load(Some(path.clone()));
+    database.clear();
+    database.record(&roots, OsStr::from_bytes(b"root/caf\xe9\nnew\tline"), now);
+    database.save(now).unwrap();
+    let database = FrecencyDatabase::load(Some(path.clone()));
+    assert_eq!(database.entries.len(), 1);
+    assert_eq!(database.entries[0].root, OsStr::new("/root\twith\\tab"));
+    assert_eq!(database.entries[0].path, OsStr::from_bytes(b"caf\xe9\nnew\tline"));
+    assert_eq!(database.boosts(&roots, now).keys().collect::<Vec<&String>>(), vec!["root/caf\u{FFFD}\nnew\tline"]);
+    let _ = fs::remove_file(&path);
+}
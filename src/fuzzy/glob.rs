@@ -0,0 +1,137 @@
+use regex::{self, Regex};
+
+pub struct Glob {
+    regex: Regex,
+}
+
+impl Glob {
+
+    pub fn new(pattern: &str) -> Result<Glob, String> {
+        match Regex::new(&glob_to_regex(pattern)) {
+            Ok(regex) => Ok(Glob { regex: regex }),
+            Err(error) => Err(format!("invalid glob '{}': {}", pattern, error)),
+        }
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        self.regex.is_match(path)
+    }
+}
+
+impl Clone for Glob {
+
+    fn clone(&self) -> Glob {
+        Glob { regex: self.regex.clone() }
+    }
+}
+
+// `*` and `?` never cross a `/`, `**` does when it makes up a whole path segment.
+fn glob_to_regex(pattern: &str) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut regex_phrase = String::from("^");
+    let mut index = 0;
+    while index < chars.len() {
+        match chars[index] {
+            '*' => {
+                if index + 1 < chars.len() && chars[index + 1] == '*' {
+                    let starts_segment = index == 0 || chars[index - 1] == '/';
+                    let ends_pattern = index + 2 == chars.len();
+                    let ends_segment = index + 2 < chars.len() && chars[index + 2] == '/';
+                    if starts_segment && ends_segment {
+                        regex_phrase.push_str("(?:.*/)?");
+                        index += 3;
+                    } else if starts_segment && ends_pattern {
+                        regex_phrase.push_str(".*");
+                        index += 2;
+                    } else {
+                        regex_phrase.push_str("[^/]*");
+                        index += 2;
+                    }
+                    continue;
+                }
+                regex_phrase.push_str("[^/]*");
+            }
+            '?' => { regex_phrase.push_str("[^/]"); }
+            '[' => {
+                match character_class_end(&chars, index) {
+                    Some(end) => {
+                        regex_phrase.push_str(&character_class(&chars[(index + 1)..end]));
+                        index = end;
+                    }
+                    None => { regex_phrase.push_str("\\["); }
+                }
+            }
+            '\\' if index + 1 < chars.len() => {
+                index += 1;
+                regex_phrase.push_str(&regex::quote(&chars[index].to_string()));
+            }
+            character => { regex_phrase.push_str(&regex::quote(&character.to_string())); }
+        }
+        index += 1;
+    }
+    regex_phrase.push('$');
+    regex_phrase
+}
+
+fn character_class_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut index = start + 1;
+    if index < chars.len() && (chars[index] == '!' || chars[index] == '^') {
+        index += 1;
+    }
+    if index < chars.len() && chars[index] == ']' {
+        index += 1;
+    }
+    while index < chars.len() {
+        if chars[index] == ']' {
+            return Some(index);
+        }
+        index += 1;
+    }
+    None
+}
+
+fn character_class(content: &[char]) -> String {
+    let mut class = String::from("[");
+    let mut index = 0;
+    if !content.is_empty() && (content[0] == '!' || content[0] == '^') {
+        class.push('^');
+        index = 1;
+    }
+    while index < content.len() {
+        match content[index] {
+            '\\' | '[' | ']' | '^' | '&' | '~' => {
+                class.push('\\');
+                class.push(content[index]);
+            }
+            character => { class.push(character); }
+        }
+        index += 1;
+    }
+    class.push(']');
+    class
+}
+
+#[test]
+fn a_star_does_not_cross_directories() {
+    let glob = Glob::new("*.rs").unwrap();
+    assert!(glob.is_match("main.rs"));
+    assert!(!glob.is_match("src/main.rs"));
+}
+
+#[test]
+fn a_double_star_matches_any_number_of_directories() {
+    let glob = Glob::new("**/*_test.rs").unwrap();
+    assert!(glob.is_match("parser_test.rs"));
+    assert!(glob.is_match("src/fuzzy/parser_test.rs"));
+    let glob = Glob::new("vendor/**").unwrap();
+    assert!(glob.is_match("vendor/a/b.c"));
+    assert!(!glob.is_match("vendored/a"));
+}
+
+#[test]
+fn character_classes_and_literals_are_supported() {
+    let glob = Glob::new("file[0-9].(txt)").unwrap();
+    assert!(glob.is_match("file1.(txt)"));
+    assert!(!glob.is_match("filea.(txt)"));
+    assert!(Glob::new("[!a]bc").unwrap().is_match("xbc"));
+}
@@ -0,0 +1,148 @@
+use std::io;
+use std::path::PathBuf;
+use fuzzy::data_file;
+
+pub const MAX_ENTRIES: usize = 1000;
+
+// Queries that ended in a selection, oldest first, one per line in `path`.
+// `previous` and `next` walk through them from the query being typed.
+pub struct History {
+    path: Option<PathBuf>,
+    entries: Vec<String>,
+    max_entries: usize,
+    position: usize,
+    draft: String,
+}
+
+impl History {
+
+    // A history that can't be read starts out empty.
+    pub fn load(path: Option<PathBuf>, max_entries: usize) -> History {
+        let mut entries = read_entries(&path);
+        cap(&mut entries, max_entries);
+        let position = entries.len();
+        History { path: path, entries: entries, max_entries: max_entries, position: position, draft: String::new() }
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        data_file::default_path("history")
+    }
+
+    #[cfg(test)]
+    pub fn entries(&self) -> &Vec<String> {
+        &self.entries
+    }
+
+    // Moves an existing entry to the end rather than storing it twice. The file is read
+    // again first, so what another fuzzy open at the same time saved is kept.
+    pub fn add(&mut self, query: &str) -> io::Result<()> {
+        if query.is_empty() || query.contains('\n') {
+            return Ok(());
+        }
+        if self.path.is_some() {
+            self.entries = read_entries(&self.path);
+        }
+        self.entries.retain(|entry| entry != query);
+        self.entries.push(query.to_string());
+        cap(&mut self.entries, self.max_entries);
+        self.position = self.entries.len();
+        self.save()
+    }
+
+    pub fn previous(&mut self, current: &str) -> Option<String> {
+        if self.position == 0 {
+            return None;
+        }
+        if self.position == self.entries.len() {
+            self.draft = current.to_string();
+        }
+        self.position -= 1;
+        Some(self.entries[self.position].clone())
+    }
+
+    // Going past the newest entry brings back what was being typed.
+    pub fn next(&mut self) -> Option<String> {
+        if self.position >= self.entries.len() {
+            return None;
+        }
+        self.position += 1;
+        if self.position == self.entries.len() {
+            Some(self.draft.clone())
+        } else {
+            Some(self.entries[self.position].clone())
+        }
+    }
+
+    // ------- private methods -------- //
+
+    fn save(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let mut content = String::new();
+        for entry in self.entries.iter() {
+            content.push_str(entry);
+            content.push('\n');
+        }
+        data_file::write(path, content.as_bytes())
+    }
+}
+
+fn read_entries(path: &Option<PathBuf>) -> Vec<String> {
+    let content = data_file::read(path);
+    let content = String::from_utf8_lossy(&content);
+    content.lines().filter(|line| !line.is_empty()).map(|line| line.to_string()).collect()
+}
+
+// Keeps the newest `max_entries`.
+fn cap(entries: &mut Vec<String>, max_entries: usize) {
+    if entries.len() > max_entries {
+        let excess = entries.len() - max_entries;
+        entries.drain(..excess);
+    }
+}
+
+#[test]
+fn entries_are_deduplicated_capped_and_saved() {
+    use std::env;
+    use std::fs;
+    let path = env::temp_dir().join(format!("fuzzy_history_test_{}", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let mut history = History::load(Some(path.clone()), 3);
+    for query in ["main", "lib", "main", "view", "mod"].iter() {
+        history.add(query).unwrap();
+    }
+    assert_eq!(history.entries(), &vec!["main", "view", "mod"]);
+    assert_eq!(History::load(Some(path.clone()), 2).entries(), &vec!["view", "mod"]);
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn previous_and_next_walk_back_to_the_draft() {
+    let mut history = History::load(None, MAX_ENTRIES);
+    history.add("main").unwrap();
+    history.add("lib").unwrap();
+    assert_eq!(history.next(), None);
+    assert_eq!(history.previous("res"), Some(String::from("lib")));
+    assert_eq!(history.previous("lib"), Some(String::from("main")));
+    assert_eq!(history.previous("main"), None);
+    assert_eq!(history.next(), Some(String::from("lib")));
+    assert_eq!(history.next(), Some(String::from("res")));
+    assert_eq!(history.next(), None);
+}
+
+#[test]
+fn sessions_open_at_the_same_time_keep_each_others_queries() {
+    use std::env;
+    use std::fs;
+    let path = env::temp_dir().join(format!("fuzzy_history_sessions_{}", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let mut first = History::load(Some(path.clone()), MAX_ENTRIES);
+    let mut second = History::load(Some(path.clone()), MAX_ENTRIES);
+    first.add("main").unwrap();
+    second.add("lib").unwrap();
+    first.add("view").unwrap();
+    assert_eq!(History::load(Some(path.clone()), MAX_ENTRIES).entries(), &vec!["main", "lib", "view"]);
+    let _ = fs::remove_file(&path);
+}
@@ -0,0 +1,357 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use fuzzy::glob::Glob;
+
+struct IgnorePattern {
+    glob: Glob,
+    negated: bool,
+    directory_only: bool,
+    anchored: bool,
+}
+
+pub struct IgnoreFile {
+    base_dir: PathBuf,
+    patterns: Vec<IgnorePattern>,
+}
+
+impl IgnoreFile {
+
+    pub fn from_path(path: &Path, base_dir: &Path) -> Option<IgnoreFile> {
+        match read_to_string(path) {
+            Some(content) => Some(IgnoreFile::parse(&content, base_dir)),
+            None => None,
+        }
+    }
+
+    pub fn parse(content: &str, base_dir: &Path) -> IgnoreFile {
+        let mut patterns = vec![];
+        for line in content.lines() {
+            match parse_line(line) {
+                Some(pattern) => patterns.push(pattern),
+                None => {}
+            }
+        }
+        IgnoreFile { base_dir: base_dir.to_path_buf(), patterns: patterns }
+    }
+
+    // Some(true) when ignored, Some(false) when explicitly re-included, None when no pattern applies.
+    pub fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative_path = match path.strip_prefix(&self.base_dir) {
+            Ok(relative_path) => relative_path,
+            Err(_) => return None,
+        };
+        // patterns only ever match the valid UTF-8 parts of a name
+        let relative_path = relative_path.to_string_lossy();
+        let relative_path = &relative_path[..];
+        let basename = match relative_path.rfind('/') {
+            Some(index) => &relative_path[(index + 1)..],
+            None => relative_path,
+        };
+        for pattern in self.patterns.iter().rev() {
+            if pattern.directory_only && !is_dir {
+                continue;
+            }
+            let candidate = if pattern.anchored { relative_path } else { basename };
+            if pattern.glob.is_match(candidate) {
+                return Some(!pattern.negated);
+            }
+        }
+        None
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+}
+
+// `stamp` changes whenever one of the ignore files the stack was built from, or would
+// have been built from had it existed, is created, modified or removed.
+#[derive(Clone)]
+pub struct IgnoreStack {
+    files: Vec<Arc<IgnoreFile>>,
+    stamp: u64,
+}
+
+impl IgnoreStack {
+
+    pub fn new() -> IgnoreStack {
+        IgnoreStack { files: vec![], stamp: 0 }
+    }
+
+    // Rules that apply to a scan root before its own ignore files are read: global git
+    // excludes, the repository's info/exclude and the .gitignore files of any parent
+    // directories inside the same repository.
+    pub fn for_root(root_dir: &Path) -> IgnoreStack {
+        let mut stack = IgnoreStack::new();
+        let repository_root = find_repository_root(root_dir);
+        let base_dir = repository_root.clone().unwrap_or(root_dir.to_path_buf());
+        match global_excludes_file(repository_root.as_ref()) {
+            Some(path) => stack.push_file(&path, &base_dir),
+            None => {}
+        }
+        match repository_root {
+            Some(repository_root) => {
+                stack.push_file(&repository_root.join(".git").join("info").join("exclude"), &repository_root);
+                let mut parents = vec![];
+                let mut current = root_dir.parent();
+                while let Some(dir) = current {
+                    if !dir.starts_with(&repository_root) {
+                        break;
+                    }
+                    parents.push(dir.to_path_buf());
+                    current = dir.parent();
+                }
+                for dir in parents.iter().rev() {
+                    stack = stack.descend(dir);
+                }
+            }
+            None => {}
+        }
+        stack
+    }
+
+    pub fn descend(&self, dir: &Path) -> IgnoreStack {
+        let mut stack = self.clone();
+        stack.push_file(&dir.join(".gitignore"), dir);
+        stack.push_file(&dir.join(".ignore"), dir);
+        stack
+    }
+
+    // The stamp `descend(dir)` would have, without reading the files.
+    pub fn descended_stamp(&self, dir: &Path) -> u64 {
+        let stamp = mix(self.stamp, file_stamp(&dir.join(".gitignore")));
+        mix(stamp, file_stamp(&dir.join(".ignore")))
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for file in self.files.iter().rev() {
+            match file.matched(path, is_dir) {
+                Some(ignored) => return ignored,
+                None => {}
+            }
+        }
+        false
+    }
+
+    // ------- private methods -------- //
+
+    fn push_file(&mut self, path: &Path, base_dir: &Path) {
+        self.stamp = mix(self.stamp, file_stamp(path));
+        match IgnoreFile::from_path(path, base_dir) {
+            Some(file) => {
+                if !file.is_empty() {
+                    self.files.push(Arc::new(file));
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+fn file_stamp(path: &Path) -> u64 {
+    match fs::metadata(path) {
+        Ok(metadata) => mix(mix(metadata.mtime() as u64, metadata.mtime_nsec() as u64), metadata.len()),
+        Err(_) => 0,
+    }
+}
+
+// One step of FNV-1a over the bytes of `value`.
+fn mix(stamp: u64, value: u64) -> u64 {
+    let mut stamp = stamp;
+    for shift in 0..8 {
+        stamp = (stamp ^ ((value >> (shift * 8)) & 0xff)).wrapping_mul(0x100000001b3);
+    }
+    stamp
+}
+
+fn parse_line(line: &str) -> Option<IgnorePattern> {
+    let mut line = trim_trailing_spaces(line);
+    if line.is_empty() || line.starts_with("#") {
+        return None;
+    }
+    let mut negated = false;
+    if line.starts_with("!") {
+        negated = true;
+        line = line[1..].to_string();
+    } else if line.starts_with("\\!") || line.starts_with("\\#") {
+        line = line[1..].to_string();
+    }
+    let mut directory_only = false;
+    if line.ends_with("/") {
+        directory_only = true;
+        line.pop();
+    }
+    let mut anchored = line.contains('/');
+    if line.starts_with("/") {
+        line = line[1..].to_string();
+    } else if line.starts_with("**/") {
+        // "**/foo" is equivalent to an unanchored "foo"
+        if !line[3..].contains('/') {
+            line = line[3..].to_string();
+            anchored = false;
+        }
+    }
+    if line.is_empty() {
+        return None;
+    }
+    match Glob::new(&line) {
+        Ok(glob) => Some(IgnorePattern { glob: glob, negated: negated, directory_only: directory_only, anchored: anchored }),
+        Err(_) => None,
+    }
+}
+
+fn trim_trailing_spaces(line: &str) -> String {
+    let mut trimmed = line.trim_right_matches('\r').to_string();
+    while trimmed.ends_with(" ") && !trimmed.ends_with("\\ ") {
+        trimmed.pop();
+    }
+    if trimmed.ends_with("\\ ") {
+        let length = trimmed.len();
+        trimmed.remove(length - 2);
+    }
+    trimmed
+}
+
+fn find_repository_root(dir: &Path) -> Option<PathBuf> {
+    let mut current = Some(dir);
+    while let Some(dir) = current {
+        if dir.join(".git").exists() {
+            return Some(dir.to_path_buf());
+        }
+        current = dir.parent();
+    }
+    None
+}
+
+fn global_excludes_file(repository_root: Option<&PathBuf>) -> Option<PathBuf> {
+    let home = env::var("HOME").ok().map(PathBuf::from);
+    let config_home = match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) => Some(PathBuf::from(dir)),
+        Err(_) => home.as_ref().map(|home| home.join(".config")),
+    };
+    let mut config_files = vec![];
+    if let Some(ref config_home) = config_home {
+        config_files.push(config_home.join("git").join("config"));
+    }
+    if let Some(ref home) = home {
+        config_files.push(home.join(".gitconfig"));
+    }
+    if let Some(repository_root) = repository_root {
+        config_files.push(repository_root.join(".git").join("config"));
+    }
+    let mut excludes_file = None;
+    for config_file in config_files.iter() {
+        match read_to_string(config_file).and_then(|content| parse_excludes_file(&content)) {
+            Some(value) => excludes_file = Some(expand_home(&value, home.as_ref())),
+            None => {}
+        }
+    }
+    match excludes_file {
+        Some(excludes_file) => Some(excludes_file),
+        None => config_home.map(|config_home| config_home.join("git").join("ignore")),
+    }
+}
+
+fn parse_excludes_file(config: &str) -> Option<String> {
+    let mut in_core_section = false;
+    let mut value = None;
+    for line in config.lines() {
+        let line = line.trim();
+        if line.starts_with("[") {
+            in_core_section = line.trim_matches(|c| c == '[' || c == ']').trim().to_lowercase() == "core";
+        } else if in_core_section {
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim().to_lowercase();
+            if key == "excludesfile" {
+                match parts.next() {
+                    Some(raw_value) => value = Some(raw_value.trim().trim_matches('"').to_string()),
+                    None => {}
+                }
+            }
+        }
+    }
+    value
+}
+
+fn expand_home(path: &str, home: Option<&PathBuf>) -> PathBuf {
+    match home {
+        Some(home) if path.starts_with("~/") => home.join(&path[2..]),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn read_to_string(path: &Path) -> Option<String> {
+    let mut content = String::new();
+    match File::open(path) {
+        Ok(mut file) => {
+            match file.read_to_string(&mut content) {
+                Ok(_) => Some(content),
+                Err(_) => None,
+            }
+        }
+        Err(_) => None,
+    }
+}
+
+#[test]
+fn unanchored_patterns_match_at_any_depth() {
+    let file = IgnoreFile::parse("target\n*.log\n", Path::new("/repo"));
+    assert_eq!(file.matched(Path::new("/repo/target"), true), Some(true));
+    assert_eq!(file.matched(Path::new("/repo/sub/target"), true), Some(true));
+    assert_eq!(file.matched(Path::new("/repo/sub/debug.log"), false), Some(true));
+    assert_eq!(file.matched(Path::new("/repo/src/main.rs"), false), None);
+}
+
+#[test]
+fn anchored_and_directory_only_patterns() {
+    let file = IgnoreFile::parse("/build\ndocs/*.html\nnode_modules/\n", Path::new("/repo"));
+    assert_eq!(file.matched(Path::new("/repo/build"), true), Some(true));
+    assert_eq!(file.matched(Path::new("/repo/sub/build"), true), None);
+    assert_eq!(file.matched(Path::new("/repo/docs/index.html"), false), Some(true));
+    assert_eq!(file.matched(Path::new("/repo/node_modules"), false), None);
+    assert_eq!(file.matched(Path::new("/repo/node_modules"), true), Some(true));
+}
+
+#[test]
+fn later_negations_re_include_files() {
+    let file = IgnoreFile::parse("# logs\n*.log\n!keep.log\n", Path::new("/repo"));
+    assert_eq!(file.matched(Path::new("/repo/debug.log"), false), Some(true));
+    assert_eq!(file.matched(Path::new("/repo/keep.log"), false), Some(false));
+}
+
+#[test]
+fn deeper_ignore_files_take_precedence() {
+    let mut stack = IgnoreStack::new();
+    stack.files.push(Arc::new(IgnoreFile::parse("*.gen\n", Path::new("/repo"))));
+    stack.files.push(Arc::new(IgnoreFile::parse("!wanted.gen\n", Path::new("/repo/sub"))));
+    assert!(stack.is_ignored(Path::new("/repo/sub/other.gen"), false));
+    assert!(!stack.is_ignored(Path::new("/repo/sub/wanted.gen"), false));
+}
+
+#[test]
+fn stamps_change_with_the_ignore_files() {
+    use std::io::Write;
+    let dir = env::temp_dir().join(format!("fuzzy_ignore_stamp_test_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let stack = IgnoreStack::new();
+    let missing = stack.descend(&dir).stamp;
+    assert_eq!(stack.descended_stamp(&dir), missing);
+    File::create(dir.join(".ignore")).unwrap().write_all(b"*.log\n").unwrap();
+    let created = stack.descend(&dir).stamp;
+    assert!(created != missing);
+    assert_eq!(stack.descended_stamp(&dir), created);
+    fs::remove_file(dir.join(".ignore")).unwrap();
+    assert_eq!(stack.descended_stamp(&dir), missing);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn core_excludes_file_is_read_from_git_config() {
+    let config = "[user]\n  name = me\n[core]\n  excludesFile = ~/.gitignore_global\n";
+    assert_eq!(parse_excludes_file(config), Some(String::from("~/.gitignore_global")));
+}
@@ -0,0 +1,179 @@
+use std::cmp;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Height {
+    Lines(usize),
+    Percent(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PreviewPosition {
+    Right,
+    Bottom,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Area {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+// Where the prompt and the results go on screen. By default the prompt is the top
+// row with results below it, `reverse` puts the prompt at the bottom of the used rows.
+// With `cycle` moving past either end of the results wraps around to the other.
+// A preview takes the right half of the results, or the half furthest from the prompt.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    pub reverse: bool,
+    pub height: Option<Height>,
+    pub cycle: bool,
+    pub preview: Option<PreviewPosition>,
+}
+
+impl Layout {
+
+    pub fn new() -> Layout {
+        Layout { reverse: false, height: None, cycle: false, preview: None }
+    }
+
+    pub fn rows(&self, terminal_height: usize) -> usize {
+        let rows = match self.height {
+            Some(Height::Lines(lines)) => lines,
+            Some(Height::Percent(percent)) => terminal_height * percent / 100,
+            None => terminal_height,
+        };
+        cmp::min(cmp::max(rows, 2), terminal_height)
+    }
+
+    pub fn prompt_row(&self, terminal_height: usize) -> usize {
+        if self.reverse {
+            self.rows(terminal_height).saturating_sub(1)
+        } else {
+            0
+        }
+    }
+
+    pub fn max_results(&self, terminal_height: usize) -> usize {
+        let rows = self.rows(terminal_height);
+        match self.preview {
+            Some(PreviewPosition::Bottom) => (rows - rows / 2).saturating_sub(1),
+            _ => rows.saturating_sub(1),
+        }
+    }
+
+    // The screen row of the result shown at `index` (0 is the best match).
+    pub fn result_row(&self, index: usize, terminal_height: usize) -> usize {
+        if self.reverse {
+            self.rows(terminal_height).saturating_sub(index + 2)
+        } else {
+            index + 1
+        }
+    }
+
+    // The columns the prompt and results can use.
+    pub fn results_width(&self, terminal_width: usize) -> usize {
+        match self.preview {
+            Some(PreviewPosition::Right) => terminal_width / 2,
+            _ => terminal_width,
+        }
+    }
+
+    pub fn preview_area(&self, terminal_width: usize, terminal_height: usize) -> Option<Area> {
+        let rows = self.rows(terminal_height);
+        match self.preview {
+            Some(PreviewPosition::Right) => {
+                // one column between the results and the preview for a border
+                let x = self.results_width(terminal_width) + 1;
+                let y = if self.reverse { 0 } else { 1 };
+                Some(Area { x: x, y: y, width: terminal_width.saturating_sub(x), height: rows.saturating_sub(1) })
+            }
+            Some(PreviewPosition::Bottom) => {
+                // one row between the results and the preview for a border
+                let height = (rows / 2).saturating_sub(1);
+                let y = if self.reverse { 0 } else { rows - height };
+                Some(Area { x: 0, y: y, width: terminal_width, height: height })
+            }
+            None => None,
+        }
+    }
+}
+
+// The first result to show so that `cursor` stays on screen, scrolling as little as possible.
+pub fn scroll_offset(cursor: usize, offset: usize, visible: usize) -> usize {
+    if cursor < offset {
+        cursor
+    } else if visible > 0 && cursor >= offset + visible {
+        cursor + 1 - visible
+    } else {
+        offset
+    }
+}
+
+// The start and length of the scrollbar thumb, when there are more results than fit.
+pub fn scrollbar(total: usize, offset: usize, visible: usize) -> Option<(usize, usize)> {
+    if visible == 0 || total <= visible {
+        return None;
+    }
+    let size = cmp::max(visible * visible / total, 1);
+    let start = cmp::min(offset * visible / total, visible - size);
+    Some((start, size))
+}
+
+#[test]
+fn by_default_results_are_listed_below_the_prompt() {
+    let layout = Layout::new();
+    assert_eq!(layout.prompt_row(20), 0);
+    assert_eq!(layout.result_row(0, 20), 1);
+    assert_eq!(layout.max_results(20), 19);
+}
+
+#[test]
+fn reversed_layouts_list_results_upwards_from_the_prompt() {
+    let layout = Layout { reverse: true, height: Some(Height::Lines(10)), ..Layout::new() };
+    assert_eq!(layout.prompt_row(40), 9);
+    assert_eq!(layout.result_row(0, 40), 8);
+    assert_eq!(layout.result_row(8, 40), 0);
+    assert_eq!(layout.max_results(40), 9);
+}
+
+#[test]
+fn heights_are_limited_to_the_terminal() {
+    assert_eq!(Layout { reverse: false, height: Some(Height::Percent(50)), ..Layout::new() }.rows(30), 15);
+    assert_eq!(Layout { reverse: false, height: Some(Height::Lines(100)), ..Layout::new() }.rows(30), 30);
+}
+
+#[test]
+fn previews_share_the_screen_with_the_results() {
+    let right = Layout { preview: Some(PreviewPosition::Right), ..Layout::new() };
+    assert_eq!(right.results_width(80), 40);
+    assert_eq!(right.max_results(20), 19);
+    assert_eq!(right.preview_area(80, 20), Some(Area { x: 41, y: 1, width: 39, height: 19 }));
+
+    let bottom = Layout { preview: Some(PreviewPosition::Bottom), ..Layout::new() };
+    assert_eq!(bottom.results_width(80), 80);
+    assert_eq!(bottom.max_results(20), 9);
+    assert_eq!(bottom.preview_area(80, 20), Some(Area { x: 0, y: 11, width: 80, height: 9 }));
+
+    let reversed = Layout { reverse: true, preview: Some(PreviewPosition::Bottom), ..Layout::new() };
+    assert_eq!(reversed.prompt_row(20), 19);
+    assert_eq!(reversed.result_row(8, 20), 10);
+    assert_eq!(reversed.preview_area(80, 20), Some(Area { x: 0, y: 0, width: 80, height: 9 }));
+}
+
+#[test]
+fn the_offset_scrolls_just_enough_to_show_the_cursor() {
+    assert_eq!(scroll_offset(5, 0, 10), 0);
+    assert_eq!(scroll_offset(10, 0, 10), 1);
+    assert_eq!(scroll_offset(299, 0, 10), 290);
+    assert_eq!(scroll_offset(3, 290, 10), 3);
+}
+
+#[test]
+fn the_scrollbar_is_only_shown_when_results_overflow() {
+    assert_eq!(scrollbar(5, 0, 10), None);
+    assert_eq!(scrollbar(20, 0, 10), Some((0, 5)));
+    assert_eq!(scrollbar(20, 10, 10), Some((5, 5)));
+    assert_eq!(scrollbar(1000, 990, 10), Some((9, 1)));
+}
@@ -0,0 +1,193 @@
+// The query being typed and where the cursor is in it. Edits return whether the
+// content changed so each one can be turned into a single search phrase change.
+pub struct LineEditor {
+    content: Vec<char>,
+    cursor: usize,
+    yank_buffer: String,
+}
+
+impl LineEditor {
+
+    pub fn new(content: &str) -> LineEditor {
+        let content: Vec<char> = content.chars().collect();
+        let cursor = content.len();
+        LineEditor { content: content, cursor: cursor, yank_buffer: String::new() }
+    }
+
+    pub fn content(&self) -> String {
+        self.content.iter().cloned().collect()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn len(&self) -> usize {
+        self.content.len()
+    }
+
+    // Swaps in a whole new query, from the history, with the cursor at its end.
+    pub fn replace(&mut self, content: &str) -> bool {
+        let changed = self.content() != content;
+        self.content = content.chars().collect();
+        self.cursor = self.content.len();
+        changed
+    }
+
+    pub fn insert(&mut self, character: char) -> bool {
+        self.content.insert(self.cursor, character);
+        self.cursor += 1;
+        true
+    }
+
+    pub fn insert_str(&mut self, string: &str) -> bool {
+        for character in string.chars() {
+            self.insert(character);
+        }
+        !string.is_empty()
+    }
+
+    pub fn delete_backward(&mut self) -> bool {
+        if self.cursor == 0 {
+            return false;
+        }
+        self.cursor -= 1;
+        self.content.remove(self.cursor);
+        true
+    }
+
+    pub fn delete_forward(&mut self) -> bool {
+        if self.cursor == self.content.len() {
+            return false;
+        }
+        self.content.remove(self.cursor);
+        true
+    }
+
+    pub fn move_left(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if self.cursor < self.content.len() {
+            self.cursor += 1;
+        }
+    }
+
+    pub fn move_to_start(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_to_end(&mut self) {
+        self.cursor = self.content.len();
+    }
+
+    pub fn move_word_backward(&mut self) {
+        self.cursor = self.previous_boundary(is_word_character);
+    }
+
+    pub fn move_word_forward(&mut self) {
+        let mut cursor = self.cursor;
+        while cursor < self.content.len() && !is_word_character(self.content[cursor]) {
+            cursor += 1;
+        }
+        while cursor < self.content.len() && is_word_character(self.content[cursor]) {
+            cursor += 1;
+        }
+        self.cursor = cursor;
+    }
+
+    // Words are separated by whitespace here, so `src/fuzzy` goes in one go.
+    pub fn delete_word_backward(&mut self) -> bool {
+        let (start, cursor) = (self.previous_boundary(|character| !character.is_whitespace()), self.cursor);
+        self.kill(start, cursor)
+    }
+
+    pub fn kill_to_start(&mut self) -> bool {
+        let cursor = self.cursor;
+        self.kill(0, cursor)
+    }
+
+    pub fn kill_to_end(&mut self) -> bool {
+        let (cursor, end) = (self.cursor, self.content.len());
+        self.kill(cursor, end)
+    }
+
+    pub fn yank(&mut self) -> bool {
+        let yank_buffer = self.yank_buffer.clone();
+        self.insert_str(&yank_buffer)
+    }
+
+    // ------- private methods -------- //
+
+    fn previous_boundary<F: Fn(char) -> bool>(&self, in_word: F) -> usize {
+        let mut cursor = self.cursor;
+        while cursor > 0 && !in_word(self.content[cursor - 1]) {
+            cursor -= 1;
+        }
+        while cursor > 0 && in_word(self.content[cursor - 1]) {
+            cursor -= 1;
+        }
+        cursor
+    }
+
+    fn kill(&mut self, start: usize, end: usize) -> bool {
+        if start == end {
+            return false;
+        }
+        self.yank_buffer = self.content.drain(start..end).collect();
+        self.cursor = start;
+        true
+    }
+}
+
+fn is_word_character(character: char) -> bool {
+    character.is_alphanumeric()
+}
+
+#[test]
+fn characters_are_inserted_and_deleted_at_the_cursor() {
+    let mut editor = LineEditor::new("mainrs");
+    editor.move_left();
+    editor.move_left();
+    assert!(editor.insert('.'));
+    assert_eq!(editor.content(), "main.rs");
+    assert_eq!(editor.cursor(), 5);
+    assert!(editor.delete_backward());
+    assert!(editor.delete_forward());
+    assert_eq!(editor.content(), "mains");
+    editor.move_to_end();
+    assert!(!editor.delete_forward());
+    editor.move_to_start();
+    assert!(!editor.delete_backward());
+}
+
+#[test]
+fn the_cursor_moves_by_words() {
+    let mut editor = LineEditor::new("src/fuzzy/results_view.rs");
+    editor.move_word_backward();
+    assert_eq!(editor.cursor(), 23);
+    editor.move_word_backward();
+    assert_eq!(editor.cursor(), 18);
+    editor.move_word_backward();
+    editor.move_word_forward();
+    assert_eq!(editor.cursor(), 17);
+}
+
+#[test]
+fn killed_text_can_be_yanked_back() {
+    let mut editor = LineEditor::new("src main rs");
+    assert!(editor.delete_word_backward());
+    assert_eq!(editor.content(), "src main ");
+    editor.move_to_start();
+    assert!(editor.yank());
+    assert_eq!(editor.content(), "rssrc main ");
+    assert!(editor.kill_to_end());
+    assert_eq!(editor.content(), "rs");
+    assert!(editor.kill_to_start());
+    assert_eq!(editor.content(), "");
+    assert!(editor.yank());
+    assert_eq!(editor.content(), "rs");
+}
@@ -0,0 +1,420 @@
+use regex::Regex;
+use std::cmp::Ordering;
+use fuzzy::query::Query;
+
+const SCORE_MATCH: i32 = 16;
+const BONUS_CONSECUTIVE: i32 = 12;
+const BONUS_PATH_SEGMENT: i32 = 10;
+const BONUS_WORD_BOUNDARY: i32 = 8;
+const BONUS_CAMEL_CASE: i32 = 7;
+const BONUS_BASENAME: i32 = 6;
+const PENALTY_GAP: i32 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    pub score: i32,
+    pub positions: Vec<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CaseMode {
+    // insensitive unless the query has an uppercase character
+    Smart,
+    Sensitive,
+    Insensitive,
+}
+
+impl CaseMode {
+
+    pub fn is_sensitive_for(&self, query: &str) -> bool {
+        match *self {
+            CaseMode::Smart => query.chars().any(|character| character.is_uppercase()),
+            CaseMode::Sensitive => true,
+            CaseMode::Insensitive => false,
+        }
+    }
+
+    // Like `is_sensitive_for`, but escapes such as `\D` and classes such as `\p{Lu}` are
+    // no uppercase characters to match.
+    pub fn is_sensitive_for_regex(&self, pattern: &str) -> bool {
+        if *self != CaseMode::Smart {
+            return self.is_sensitive_for(pattern);
+        }
+        let mut characters = pattern.chars();
+        while let Some(character) = characters.next() {
+            if character == '\\' {
+                match characters.next() {
+                    // a class of one letter, or named in braces
+                    Some('p') | Some('P') => {
+                        if characters.next() == Some('{') {
+                            while characters.next().map_or(false, |character| character != '}') {}
+                        }
+                    }
+                    _ => {}
+                }
+            } else if character.is_uppercase() {
+                return true;
+            }
+        }
+        false
+    }
+
+    // smart, sensitive, insensitive and round again
+    pub fn next(&self) -> CaseMode {
+        match *self {
+            CaseMode::Smart => CaseMode::Sensitive,
+            CaseMode::Sensitive => CaseMode::Insensitive,
+            CaseMode::Insensitive => CaseMode::Smart,
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Pattern {
+    Regex(Regex),
+    Extended(Query),
+}
+
+#[derive(Clone)]
+pub struct Matcher {
+    query: String,
+    pattern: Pattern,
+    case_sensitive: bool,
+}
+
+impl Matcher {
+
+    // Terms, alternatives and anchors as described on `Query`, plain terms are exact with `exact`.
+    pub fn extended(query: &str, exact: bool, case: CaseMode) -> Result<Matcher, String> {
+        let case_sensitive = case.is_sensitive_for(query);
+        let parsed = try!(Query::parse(query, exact, case_sensitive));
+        Ok(Matcher { query: query.to_string(), pattern: Pattern::Extended(parsed), case_sensitive: case_sensitive })
+    }
+
+    pub fn regex(query: &str, case: CaseMode) -> Result<Matcher, String> {
+        let case_sensitive = case.is_sensitive_for_regex(query);
+        let flags = if case_sensitive { "" } else { "(?i)" };
+        match Regex::new(&format!("{}{}", flags, query)) {
+            Ok(regex) => Ok(Matcher { query: query.to_string(), pattern: Pattern::Regex(regex), case_sensitive: case_sensitive }),
+            Err(error) => Err(format!("invalid regex: {}", error).replace("\n", " ")),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.query.is_empty()
+    }
+
+    // Every candidate matching `self` also matches `previous`, so only its results need re-checking.
+    pub fn refines(&self, previous: &Matcher) -> bool {
+        if self.case_sensitive != previous.case_sensitive {
+            return false;
+        }
+        match (&self.pattern, &previous.pattern) {
+            // typing can turn a term into an alternative, a negation or an escaped space, or
+            // end an anchored one, all of which let more candidates through again
+            (&Pattern::Extended(_), &Pattern::Extended(_)) => {
+                self.query.starts_with(&previous.query)
+                    && !self.query.contains('|') && !self.query.contains('!')
+                    && !previous.query.contains('$') && !previous.query.ends_with('\\')
+            }
+            _ => false,
+        }
+    }
+
+    pub fn matches(&self, candidate: &str) -> Option<Match> {
+        match self.pattern {
+            Pattern::Extended(ref query) => {
+                let candidate: Vec<char> = candidate.chars().collect();
+                query.matches(&candidate)
+            }
+            Pattern::Regex(ref regex) => {
+                match regex.find(candidate) {
+                    Some((start, end)) => {
+                        let positions: Vec<usize> = candidate.char_indices()
+                            .enumerate()
+                            .filter(|&(_, (byte_index, _))| byte_index >= start && byte_index < end)
+                            .map(|(char_index, _)| char_index)
+                            .collect();
+                        // tighter matches rank first
+                        Some(Match { score: -(positions.len() as i32), positions: positions })
+                    }
+                    None => None,
+                }
+            }
+        }
+    }
+}
+
+pub fn fuzzy_match(pattern: &[char], candidate: &[char], case_sensitive: bool) -> Option<Match> {
+    if !is_subsequence(pattern, candidate, case_sensitive) {
+        return None;
+    }
+    if pattern.is_empty() {
+        return Some(Match { score: 0, positions: vec![] });
+    }
+    Some(best_match(pattern, candidate, case_sensitive))
+}
+
+fn is_subsequence(pattern: &[char], candidate: &[char], case_sensitive: bool) -> bool {
+    let mut pattern = pattern.iter().peekable();
+    for character in candidate.iter() {
+        match pattern.peek() {
+            Some(&&expected) if same_character(expected, *character, case_sensitive) => { pattern.next(); }
+            Some(_) => {}
+            None => break,
+        }
+    }
+    pattern.peek().is_none()
+}
+
+// Smith-Waterman style alignment: scores[i][j] is the best score for matching the first
+// i + 1 pattern characters with pattern[i] placed on candidate[j].
+fn best_match(pattern: &[char], candidate: &[char], case_sensitive: bool) -> Match {
+    let bonuses = position_bonuses(candidate);
+    let width = candidate.len();
+    let mut scores = vec![None; pattern.len() * width];
+    let mut previous_positions = vec![0; pattern.len() * width];
+
+    for (j, character) in candidate.iter().enumerate() {
+        if same_character(pattern[0], *character, case_sensitive) {
+            // where the query starts matters most, so its bonus counts twice
+            scores[j] = Some(SCORE_MATCH + 2 * bonuses[j] - PENALTY_GAP * (j as i32).min(8));
+        }
+    }
+    for i in 1..pattern.len() {
+        let mut running: Option<(i32, usize)> = None;
+        for j in 1..width {
+            // best earlier placement of pattern[i - 1], penalised by the gap it leaves
+            running = match (running, scores[(i - 1) * width + j - 1]) {
+                (Some((score, k)), Some(previous)) if score - PENALTY_GAP >= previous => Some((score - PENALTY_GAP, k)),
+                (_, Some(previous)) => Some((previous, j - 1)),
+                (Some((score, k)), None) => Some((score - PENALTY_GAP, k)),
+                (None, None) => None,
+            };
+            if !same_character(pattern[i], candidate[j], case_sensitive) {
+                continue;
+            }
+            let mut best = running;
+            if let Some(previous) = scores[(i - 1) * width + j - 1] {
+                let consecutive = previous + BONUS_CONSECUTIVE;
+                if best.map_or(true, |(score, _)| consecutive > score) {
+                    best = Some((consecutive, j - 1));
+                }
+            }
+            if let Some((score, k)) = best {
+                scores[i * width + j] = Some(score + SCORE_MATCH + bonuses[j]);
+                previous_positions[i * width + j] = k;
+            }
+        }
+    }
+
+    let last_row = (pattern.len() - 1) * width;
+    let mut best_score = i32::min_value();
+    let mut best_end = 0;
+    for j in 0..width {
+        if let Some(score) = scores[last_row + j] {
+            if score > best_score {
+                best_score = score;
+                best_end = j;
+            }
+        }
+    }
+    let mut positions = vec![best_end];
+    let mut position = best_end;
+    for i in (1..pattern.len()).rev() {
+        position = previous_positions[i * width + position];
+        positions.push(position);
+    }
+    positions.reverse();
+    Match { score: best_score, positions: positions }
+}
+
+// Scores every occurrence of the pattern the way `best_match` would score a consecutive run.
+pub fn substring_match(pattern: &[char], candidate: &[char], case_sensitive: bool) -> Option<Match> {
+    if pattern.is_empty() {
+        return Some(Match { score: 0, positions: vec![] });
+    }
+    if pattern.len() > candidate.len() {
+        return None;
+    }
+    let bonuses = position_bonuses(candidate);
+    let mut best: Option<Match> = None;
+    for start in 0..(candidate.len() - pattern.len() + 1) {
+        if let Some(found) = score_substring(pattern, candidate, &bonuses, start, case_sensitive) {
+            if best.as_ref().map_or(true, |best| found.score > best.score) {
+                best = Some(found);
+            }
+        }
+    }
+    best
+}
+
+// The pattern has to be found right at `start`.
+pub fn anchored_match(pattern: &[char], candidate: &[char], start: usize, case_sensitive: bool) -> Option<Match> {
+    if start + pattern.len() > candidate.len() {
+        return None;
+    }
+    score_substring(pattern, candidate, &position_bonuses(candidate), start, case_sensitive)
+}
+
+fn score_substring(pattern: &[char], candidate: &[char], bonuses: &[i32], start: usize, case_sensitive: bool) -> Option<Match> {
+    let found = pattern.iter().enumerate().all(|(offset, expected)| same_character(*expected, candidate[start + offset], case_sensitive));
+    if !found || pattern.is_empty() {
+        return None;
+    }
+    let mut score = SCORE_MATCH + 2 * bonuses[start] - PENALTY_GAP * (start as i32).min(8);
+    for position in (start + 1)..(start + pattern.len()) {
+        score += SCORE_MATCH + BONUS_CONSECUTIVE + bonuses[position];
+    }
+    Some(Match { score: score, positions: (start..(start + pattern.len())).collect() })
+}
+
+// Best score first, then the shorter candidate, then alphabetical.
+pub fn compare_ranked(a_score: i32, a: &str, b_score: i32, b: &str) -> Ordering {
+    match b_score.cmp(&a_score) {
+        Ordering::Equal => {
+            match a.len().cmp(&b.len()) {
+                Ordering::Equal => a.cmp(b),
+                other => other,
+            }
+        }
+        other => other,
+    }
+}
+
+fn position_bonuses(candidate: &[char]) -> Vec<i32> {
+    let basename_start = match candidate.iter().rposition(|c| *c == '/') {
+        Some(index) => index + 1,
+        None => 0,
+    };
+    let mut bonuses = vec![];
+    for (index, character) in candidate.iter().enumerate() {
+        let mut bonus = 0;
+        if index == 0 || candidate[index - 1] == '/' {
+            bonus += BONUS_PATH_SEGMENT;
+        } else {
+            let previous = candidate[index - 1];
+            if is_word_separator(previous) && !is_word_separator(*character) {
+                bonus += BONUS_WORD_BOUNDARY;
+            } else if previous.is_lowercase() && character.is_uppercase() {
+                bonus += BONUS_CAMEL_CASE;
+            } else if !previous.is_numeric() && character.is_numeric() {
+                bonus += BONUS_CAMEL_CASE;
+            }
+        }
+        if index >= basename_start {
+            bonus += BONUS_BASENAME;
+        }
+        bonuses.push(bonus);
+    }
+    bonuses
+}
+
+fn is_word_separator(character: char) -> bool {
+    character == '_' || character == '-' || character == '.' || character == ' '
+}
+
+// Without case sensitivity `expected` comes from a lowercased query.
+fn same_character(expected: char, actual: char, case_sensitive: bool) -> bool {
+    if case_sensitive {
+        expected == actual
+    } else {
+        expected == lowercase(actual)
+    }
+}
+
+pub fn lowercase(character: char) -> char {
+    character.to_lowercase().next().unwrap_or(character)
+}
+
+#[cfg(test)]
+fn fuzzy(query: &str) -> Matcher {
+    Matcher::extended(query, false, CaseMode::Smart).unwrap()
+}
+
+#[test]
+fn candidates_must_contain_the_query_in_order() {
+    let matcher = fuzzy("abc");
+    assert!(matcher.matches("a/b/c").is_some());
+    assert!(matcher.matches("AxBxC").is_some());
+    assert!(matcher.matches("cba").is_none());
+}
+
+#[test]
+fn consecutive_characters_score_higher_than_scattered_ones() {
+    let matcher = fuzzy("main");
+    let consecutive = matcher.matches("src/main.rs").unwrap();
+    let scattered = matcher.matches("src/my_animation.rs").unwrap();
+    assert!(consecutive.score > scattered.score);
+    assert_eq!(consecutive.positions, vec![4, 5, 6, 7]);
+}
+
+#[test]
+fn boundaries_camel_humps_and_basenames_are_rewarded() {
+    let matcher = fuzzy("rv");
+    assert!(matcher.matches("results_view.rs").unwrap().score > matcher.matches("carvings.rs").unwrap().score);
+    assert!(matcher.matches("ResultsView.rs").unwrap().score > matcher.matches("carvings.rs").unwrap().score);
+    let matcher = fuzzy("term");
+    assert!(matcher.matches("src/fuzzy/terminal.rs").unwrap().score > matcher.matches("term/src/fuzzy/lib.rs").unwrap().score);
+}
+
+#[test]
+fn query_characters_are_never_treated_as_regex_syntax() {
+    let matcher = fuzzy("(.[");
+    assert!(matcher.matches("a(b.c[d").is_some());
+    assert!(matcher.matches("abcd").is_none());
+}
+
+#[test]
+fn exact_mode_only_matches_unbroken_substrings() {
+    let matcher = fuzzy("'view");
+    assert_eq!(matcher.matches("src/results_view.rs").unwrap().positions, vec![12, 13, 14, 15]);
+    assert!(matcher.matches("src/very_interesting_words.rs").is_none());
+}
+
+#[test]
+fn regex_mode_reports_invalid_patterns_instead_of_panicking() {
+    assert!(Matcher::regex("src/(main", CaseMode::Smart).is_err());
+    let matcher = Matcher::regex("^src/.*\\.rs$", CaseMode::Smart).unwrap();
+    assert_eq!(matcher.matches("src/lib.rs").unwrap().positions.len(), 10);
+    assert!(matcher.matches("src/lib.toml").is_none());
+}
+
+#[test]
+fn ties_are_broken_by_length_then_alphabetically() {
+    assert_eq!(compare_ranked(10, "b", 10, "aa"), Ordering::Less);
+    assert_eq!(compare_ranked(10, "a", 10, "b"), Ordering::Less);
+    assert_eq!(compare_ranked(5, "a", 10, "b"), Ordering::Greater);
+}
+
+#[test]
+fn extended_queries_only_refine_when_no_more_candidates_can_match() {
+    let refines = |query: &str, previous: &str| {
+        Matcher::extended(query, false, CaseMode::Smart).unwrap().refines(&Matcher::extended(previous, false, CaseMode::Smart).unwrap())
+    };
+    assert!(refines("src ma", "src m"));
+    assert!(refines("rs$", "rs"));
+    assert!(!refines("rs | toml", "rs |x"));
+    assert!(!refines("rs$x", "rs$"));
+    assert!(!refines("!test", "!tes"));
+    assert!(!refines("a\\ b", "a\\"));
+}
+
+#[test]
+fn smart_case_only_matches_case_sensitively_with_uppercase_in_the_query() {
+    let matches = |query: &str, case: CaseMode, candidate: &str| Matcher::extended(query, false, case).unwrap().matches(candidate).is_some();
+    assert!(matches("makefile", CaseMode::Smart, "Makefile"));
+    assert!(matches("Makefile", CaseMode::Smart, "Makefile"));
+    assert!(!matches("Makefile", CaseMode::Smart, "makefile.old"));
+    assert!(!matches("makefile", CaseMode::Sensitive, "Makefile"));
+    assert!(matches("MAKEFILE", CaseMode::Insensitive, "makefile.old"));
+    assert!(Matcher::regex("^Make", CaseMode::Smart).unwrap().matches("makefile").is_none());
+}
+
+#[test]
+fn smart_case_ignores_regex_escapes() {
+    assert!(Matcher::regex("\\d+\\.Log$", CaseMode::Smart).unwrap().matches("1.log").is_none());
+    assert!(Matcher::regex("^\\S+\\W\\D", CaseMode::Smart).unwrap().matches("Src/main").is_some());
+    assert!(!CaseMode::Smart.is_sensitive_for_regex("\\p{Greek}\\pL\\B"));
+    assert!(CaseMode::Smart.is_sensitive_for_regex("\\p{Greek}Alpha"));
+}
@@ -5,3 +5,20 @@ pub mod file_finder;
 pub mod result_set;
 pub mod event_service;
 pub mod directory_scanner;
+pub mod ignore;
+pub mod entry_filter;
+pub mod glob;
+pub mod matcher;
+pub mod options;
+pub mod layout;
+pub mod stdin_reader;
+pub mod filter;
+pub mod preview;
+pub mod line_editor;
+pub mod data_file;
+pub mod history;
+pub mod frecency;
+pub mod query;
+pub mod watcher;
+pub mod daemon;
+pub mod cache;
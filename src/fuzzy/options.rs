@@ -0,0 +1,578 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use fuzzy::layout::{Layout, Height, PreviewPosition};
+use fuzzy::history;
+use fuzzy::cache::CacheMode;
+use fuzzy::matcher::CaseMode;
+use fuzzy::directory_scanner::EntryKind;
+use fuzzy::glob::Glob;
+
+pub const USAGE: &'static str = "Usage: fuzzy [options] [root directories...]
+       fuzzy daemon [options] [root directories...]
+
+Search files below the root directories (default: the current directory),
+or the lines piped into fuzzy when no root directories are given.
+`fuzzy daemon` keeps the files below root directories in memory and up to date,
+and serves them to fuzzy instead of scanning on every start.
+
+Options:
+  -q, --query <query>    Start with the given query
+  -f, --filter <query>   Print the ranked matches for <query> and exit, no interface
+  -e, --exact            Match the query as a substring instead of fuzzily
+      --regex            Treat the query as a regular expression (ctrl + r toggles)
+      --case <mode>      smart (the default) only matches case when the query has an
+                         uppercase character, or sensitive or insensitive (alt + c cycles)
+      --hidden           Include hidden files and directories (alt + h toggles)
+      --no-ignore        Include files ignored by .gitignore, .ignore and git excludes
+      --max-depth <n>    Don't descend more than <n> directories below a root
+      --min-depth <n>    Leave out results less than <n> directories below a root, 1 is directly inside a root
+  -t, --type <f|d|l>     Only list files, directories or symbolic links, can be repeated
+      --ext <list>       Only list files with one of these comma separated extensions
+      --glob <pattern>   Only list paths matching <pattern>, can be repeated
+      --exclude <pattern>
+                         Leave out paths matching <pattern> and don't descend into them
+      --size <+|-n[kmg]> Only list files of at least (+) or at most (-) this size
+      --changed-within <n[smhdw]>
+                         Only list entries modified within this long
+      --executable       Only list executable files
+  -L, --follow           Follow symbolic links, skipping loops
+      --no-daemon        Scan the root directories even when a daemon is running
+      --no-cache         Don't use or update the cached file lists of the root directories
+      --rebuild-cache    Scan the root directories from scratch, replacing their cached file lists
+      --print0           Separate printed results with NUL instead of newline
+      --reverse          Show the query at the bottom, with results above it
+      --height <n[%]>    Only use <n> lines (or percent) of the terminal
+      --history <file>   Keep the query history in <file> (default: ~/.local/share/fuzzy/history),
+                         ctrl + p and ctrl + n recall it
+      --history-size <n> Remember at most <n> queries (default: 1000)
+      --cycle            Wrap around when moving past the first or last result
+      --preview <command>
+                         Preview the highlighted result with the output of <command>,
+                         where {} is replaced by the result
+      --preview-window <right|bottom>
+                         Show a preview of the highlighted file or directory (alt + j/k scroll)
+      --clear-frecency   Forget which results were picked before, and exit
+      --prune-frecency   Forget picked results that are gone or weren't picked in 90 days, and exit
+  -h, --help             Print this help and exit
+  -V, --version          Print the version and exit
+
+Exit status: 0 when a result was selected (or --filter matched), 1 when nothing matched,
+2 for invalid arguments and 130 when aborted.";
+
+// The number of tab separated fields in a `ScannerOptions` key.
+pub const KEY_FIELDS: usize = 9;
+// separates the patterns of one key field
+const KEY_LIST_SEPARATOR: char = '\u{1f}';
+
+// The kinds of entries a scan lists, everything unless `--type` picks some.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EntryTypes {
+    pub files: bool,
+    pub directories: bool,
+    pub symlinks: bool,
+}
+
+impl EntryTypes {
+
+    pub fn all() -> EntryTypes {
+        EntryTypes { files: true, directories: true, symlinks: true }
+    }
+
+    pub fn includes(&self, kind: EntryKind) -> bool {
+        match kind {
+            EntryKind::File => self.files,
+            EntryKind::Directory => self.directories,
+            EntryKind::Symlink => self.symlinks,
+            EntryKind::Other => false,
+        }
+    }
+
+    // `f`, `d` and `l` for the included kinds.
+    fn to_key(&self) -> String {
+        let mut key = String::new();
+        for &(included, letter) in [(self.files, 'f'), (self.directories, 'd'), (self.symlinks, 'l')].iter() {
+            if included {
+                key.push(letter);
+            }
+        }
+        key
+    }
+
+    fn from_key(key: &str) -> EntryTypes {
+        EntryTypes { files: key.contains('f'), directories: key.contains('d'), symlinks: key.contains('l') }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScannerOptions {
+    pub respect_ignore_files: bool,
+    pub include_hidden: bool,
+    pub max_depth: Option<usize>,
+    // files directly in a root are at depth 1
+    pub min_depth: usize,
+    pub follow_symlinks: bool,
+    pub types: EntryTypes,
+    pub filters: EntryFilters,
+}
+
+// What `--ext`, `--glob`, `--exclude`, `--size`, `--changed-within` and `--executable`
+// ask for, see `EntryFilter` for how they apply.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryFilters {
+    // lowercase, without the dot
+    pub extensions: Vec<String>,
+    pub globs: Vec<String>,
+    pub excludes: Vec<String>,
+    pub size: Option<SizeLimit>,
+    // seconds
+    pub changed_within: Option<u64>,
+    pub executable: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SizeLimit {
+    AtLeast(u64),
+    AtMost(u64),
+}
+
+impl EntryFilters {
+
+    pub fn new() -> EntryFilters {
+        EntryFilters { extensions: vec![], globs: vec![], excludes: vec![], size: None, changed_within: None, executable: false }
+    }
+
+    pub fn has_metadata_filters(&self) -> bool {
+        self.size.is_some() || self.changed_within.is_some() || self.executable
+    }
+}
+
+impl ScannerOptions {
+
+    pub fn new() -> ScannerOptions {
+        ScannerOptions {
+            respect_ignore_files: true,
+            include_hidden: false,
+            max_depth: None,
+            min_depth: 0,
+            follow_symlinks: false,
+            types: EntryTypes::all(),
+            filters: EntryFilters::new(),
+        }
+    }
+
+    // Whether what a scan finds can be kept in the cache or the daemon. Sizes, times and
+    // permissions change without the directory changing, so those filters always scan.
+    pub fn is_indexable(&self) -> bool {
+        let storable = |pattern: &String| !pattern.contains(|character| character == '\t' || character == '\n' || character == KEY_LIST_SEPARATOR);
+        !self.filters.has_metadata_filters() && self.filters.globs.iter().chain(self.filters.excludes.iter()).all(storable)
+    }
+
+    // `hidden ignore follow types min-depth max-depth extensions globs excludes`, tab
+    // separated, flags as 0 or 1, the types as letters, `-` without a maximum depth and the
+    // patterns separated by a unit separator. Scans with the same key find the same files,
+    // for options that are indexable.
+    pub fn to_key(&self) -> String {
+        let flag = |value: bool| if value { "1" } else { "0" };
+        let max_depth = self.max_depth.map_or(String::from("-"), |max_depth| max_depth.to_string());
+        let separator = KEY_LIST_SEPARATOR.to_string();
+        format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}", flag(self.include_hidden), flag(self.respect_ignore_files), flag(self.follow_symlinks),
+                self.types.to_key(), self.min_depth, max_depth,
+                self.filters.extensions.join(&separator), self.filters.globs.join(&separator), self.filters.excludes.join(&separator))
+    }
+
+    pub fn from_key(key: &str) -> Option<ScannerOptions> {
+        let fields: Vec<&str> = key.split('\t').collect();
+        if fields.len() != KEY_FIELDS {
+            return None;
+        }
+        let min_depth = match fields[4].parse() {
+            Ok(min_depth) => min_depth,
+            Err(_) => return None,
+        };
+        let max_depth = match fields[5] {
+            "-" => None,
+            max_depth => match max_depth.parse() {
+                Ok(max_depth) => Some(max_depth),
+                Err(_) => return None,
+            },
+        };
+        Some(ScannerOptions {
+            include_hidden: fields[0] == "1",
+            respect_ignore_files: fields[1] == "1",
+            follow_symlinks: fields[2] == "1",
+            max_depth: max_depth,
+            min_depth: min_depth,
+            types: EntryTypes::from_key(fields[3]),
+            filters: EntryFilters {
+                extensions: key_list(fields[6]),
+                globs: key_list(fields[7]),
+                excludes: key_list(fields[8]),
+                ..EntryFilters::new()
+            },
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub root_dirs: Vec<PathBuf>,
+    pub read_stdin: bool,
+    pub query: String,
+    pub filter: Option<String>,
+    pub exact: bool,
+    pub regex_mode: bool,
+    pub case: CaseMode,
+    pub print0: bool,
+    pub preview_command: Option<String>,
+    pub history: Option<PathBuf>,
+    pub history_size: usize,
+    pub use_daemon: bool,
+    pub cache: CacheMode,
+    pub layout: Layout,
+    pub scanner: ScannerOptions,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run(Options),
+    Daemon(Options),
+    Help,
+    Version,
+    ClearFrecency,
+    PruneFrecency,
+}
+
+impl Options {
+
+    pub fn new() -> Options {
+        Options {
+            root_dirs: vec![],
+            read_stdin: false,
+            query: String::new(),
+            filter: None,
+            exact: false,
+            regex_mode: false,
+            case: CaseMode::Smart,
+            print0: false,
+            preview_command: None,
+            history: None,
+            history_size: history::MAX_ENTRIES,
+            use_daemon: true,
+            cache: CacheMode::Use,
+            layout: Layout::new(),
+            scanner: ScannerOptions::new(),
+        }
+    }
+
+    // `args` excludes the program name.
+    pub fn parse(args: Vec<String>) -> Result<Command, String> {
+        if args.first().map_or(false, |arg| arg == "daemon") {
+            return match try!(Options::parse(args[1..].to_vec())) {
+                Command::Run(options) => Ok(Command::Daemon(options)),
+                command => Ok(command),
+            };
+        }
+        let mut options = Options::new();
+        let mut args = args.into_iter();
+        let mut only_positional = false;
+        // the first `--type` replaces listing everything, the next ones add to it
+        let mut types: Option<EntryTypes> = None;
+        while let Some(arg) = args.next() {
+            if only_positional || !arg.starts_with("-") || arg == "-" {
+                options.root_dirs.push(PathBuf::from(arg));
+                continue;
+            }
+            let (flag, inline_value) = match arg.find('=') {
+                Some(index) if arg.starts_with("--") => (arg[..index].to_string(), Some(arg[(index + 1)..].to_string())),
+                _ => (arg.clone(), None),
+            };
+            match &flag[..] {
+                "--" => only_positional = true,
+                "-h" | "--help" => return Ok(Command::Help),
+                "-V" | "--version" => return Ok(Command::Version),
+                "--clear-frecency" => return Ok(Command::ClearFrecency),
+                "--prune-frecency" => return Ok(Command::PruneFrecency),
+                "-q" | "--query" => options.query = try!(value_for(&flag, inline_value, &mut args)),
+                "-f" | "--filter" => options.filter = Some(try!(value_for(&flag, inline_value, &mut args))),
+                "-e" | "--exact" => options.exact = true,
+                "--regex" => options.regex_mode = true,
+                "--print0" => options.print0 = true,
+                "--hidden" => options.scanner.include_hidden = true,
+                "--no-ignore" => options.scanner.respect_ignore_files = false,
+                "-L" | "--follow" => options.scanner.follow_symlinks = true,
+                "--no-daemon" => options.use_daemon = false,
+                "--no-cache" => options.cache = CacheMode::Off,
+                "--rebuild-cache" => options.cache = CacheMode::Rebuild,
+                "--reverse" => options.layout.reverse = true,
+                "--cycle" => options.layout.cycle = true,
+                "--max-depth" => {
+                    let value = try!(value_for(&flag, inline_value, &mut args));
+                    options.scanner.max_depth = Some(try!(parse_number(&flag, &value)));
+                }
+                "-t" | "--type" => {
+                    let value = try!(value_for(&flag, inline_value, &mut args));
+                    let mut selected = types.unwrap_or(EntryTypes { files: false, directories: false, symlinks: false });
+                    match &value[..] {
+                        "f" | "file" => selected.files = true,
+                        "d" | "directory" => selected.directories = true,
+                        "l" | "symlink" => selected.symlinks = true,
+                        _ => return Err(format!("invalid value '{}' for option '--type'", value)),
+                    }
+                    types = Some(selected);
+                }
+                "--ext" => {
+                    let value = try!(value_for(&flag, inline_value, &mut args));
+                    let extensions = value.split(',').map(|extension| extension.trim_left_matches('.').to_lowercase()).filter(|extension| !extension.is_empty());
+                    options.scanner.filters.extensions.extend(extensions);
+                }
+                "--glob" | "--exclude" => {
+                    let value = try!(value_for(&flag, inline_value, &mut args));
+                    try!(Glob::new(value.trim_left_matches('/')));
+                    if flag == "--glob" {
+                        options.scanner.filters.globs.push(value);
+                    } else {
+                        options.scanner.filters.excludes.push(value);
+                    }
+                }
+                "--size" => {
+                    let value = try!(value_for(&flag, inline_value, &mut args));
+                    options.scanner.filters.size = Some(try!(parse_size(&value)));
+                }
+                "--changed-within" => {
+                    let value = try!(value_for(&flag, inline_value, &mut args));
+                    options.scanner.filters.changed_within = Some(try!(parse_duration(&value)));
+                }
+                "--executable" => options.scanner.filters.executable = true,
+                "--min-depth" => {
+                    let value = try!(value_for(&flag, inline_value, &mut args));
+                    options.scanner.min_depth = try!(parse_number(&flag, &value));
+                }
+                "--preview" => options.preview_command = Some(try!(value_for(&flag, inline_value, &mut args))),
+                "--case" => {
+                    let value = try!(value_for(&flag, inline_value, &mut args));
+                    options.case = try!(parse_case_mode(&value));
+                }
+                "--history" => options.history = Some(PathBuf::from(try!(value_for(&flag, inline_value, &mut args)))),
+                "--history-size" => {
+                    let value = try!(value_for(&flag, inline_value, &mut args));
+                    options.history_size = try!(parse_number(&flag, &value));
+                }
+                "--preview-window" => {
+                    let value = try!(value_for(&flag, inline_value, &mut args));
+                    options.layout.preview = Some(try!(parse_preview_position(&value)));
+                }
+                "--height" => {
+                    let value = try!(value_for(&flag, inline_value, &mut args));
+                    options.layout.height = Some(try!(parse_height(&value)));
+                }
+                _ => return Err(format!("unknown option '{}'", arg)),
+            }
+        }
+        if let Some(types) = types {
+            options.scanner.types = types;
+        }
+        if !options.scanner.is_indexable() {
+            options.use_daemon = false;
+            options.cache = CacheMode::Off;
+        }
+        if options.scanner.max_depth.map_or(false, |max_depth| options.scanner.min_depth > max_depth) {
+            return Err(String::from("'--min-depth' can't be more than '--max-depth'"));
+        }
+        if options.preview_command.is_some() && options.layout.preview.is_none() {
+            options.layout.preview = Some(PreviewPosition::Right);
+        }
+        Ok(Command::Run(options))
+    }
+}
+
+fn value_for<I: Iterator<Item=String>>(flag: &str, inline_value: Option<String>, args: &mut I) -> Result<String, String> {
+    match inline_value {
+        Some(value) => Ok(value),
+        None => {
+            match args.next() {
+                Some(value) => Ok(value),
+                None => Err(format!("option '{}' requires a value", flag)),
+            }
+        }
+    }
+}
+
+fn parse_number<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    match value.parse() {
+        Ok(number) => Ok(number),
+        Err(_) => Err(format!("invalid value '{}' for option '{}'", value, flag)),
+    }
+}
+
+fn parse_height(value: &str) -> Result<Height, String> {
+    let height = if value.ends_with("%") {
+        let percent: usize = try!(parse_number("--height", &value[..(value.len() - 1)]));
+        if percent == 0 || percent > 100 {
+            return Err(format!("invalid value '{}' for option '--height'", value));
+        }
+        Height::Percent(percent)
+    } else {
+        Height::Lines(try!(parse_number("--height", value)))
+    };
+    Ok(height)
+}
+
+// `+` for at least, `-` for at most, then a number of bytes or of k, m or g, 1024 times bigger each.
+fn parse_size(value: &str) -> Result<SizeLimit, String> {
+    let invalid = || format!("invalid value '{}' for option '--size'", value);
+    if value.len() < 2 {
+        return Err(invalid());
+    }
+    let (sign, amount) = value.split_at(1);
+    let amount = amount.to_lowercase();
+    let (number, unit) = match amount.find(|character: char| !character.is_digit(10)) {
+        Some(index) => amount.split_at(index),
+        None => (&amount[..], ""),
+    };
+    let multiplier: u64 = match unit {
+        "" | "b" => 1,
+        "k" => 1024,
+        "m" => 1024 * 1024,
+        "g" => 1024 * 1024 * 1024,
+        _ => return Err(invalid()),
+    };
+    let size = match number.parse::<u64>() {
+        Ok(number) => number.saturating_mul(multiplier),
+        Err(_) => return Err(invalid()),
+    };
+    match sign {
+        "+" => Ok(SizeLimit::AtLeast(size)),
+        "-" => Ok(SizeLimit::AtMost(size)),
+        _ => Err(invalid()),
+    }
+}
+
+// A number of seconds, minutes, hours, days or weeks, in seconds.
+fn parse_duration(value: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid value '{}' for option '--changed-within'", value);
+    let (number, unit) = match value.find(|character: char| !character.is_digit(10)) {
+        Some(index) => value.split_at(index),
+        None => return Err(invalid()),
+    };
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    match number.parse::<u64>() {
+        Ok(number) => Ok(number.saturating_mul(multiplier)),
+        Err(_) => Err(invalid()),
+    }
+}
+
+fn key_list(field: &str) -> Vec<String> {
+    field.split(KEY_LIST_SEPARATOR).filter(|value| !value.is_empty()).map(|value| value.to_string()).collect()
+}
+
+fn parse_case_mode(value: &str) -> Result<CaseMode, String> {
+    match value {
+        "smart" => Ok(CaseMode::Smart),
+        "sensitive" => Ok(CaseMode::Sensitive),
+        "insensitive" => Ok(CaseMode::Insensitive),
+        _ => Err(format!("invalid value '{}' for option '--case'", value)),
+    }
+}
+
+fn parse_preview_position(value: &str) -> Result<PreviewPosition, String> {
+    match value {
+        "right" => Ok(PreviewPosition::Right),
+        "bottom" => Ok(PreviewPosition::Bottom),
+        _ => Err(format!("invalid value '{}' for option '--preview-window'", value)),
+    }
+}
+
+#[cfg(test)]
+fn parse(args: &[&str]) -> Result<Command, String> {
+    Options::parse(args.iter().map(|arg| arg.to_string()).collect())
+}
+
+#[test]
+fn without_arguments_the_defaults_are_used() {
+    assert_eq!(parse(&[]), Ok(Command::Run(Options::new())));
+}
+
+#[test]
+fn flags_values_and_root_directories_are_parsed() {
+    let mut expected = Options::new();
+    expected.root_dirs = vec![PathBuf::from("src"), PathBuf::from("/etc")];
+    expected.query = String::from("main rs");
+    expected.scanner.include_hidden = true;
+    expected.scanner.max_depth = Some(3);
+    expected.layout.reverse = true;
+    expected.layout.height = Some(Height::Percent(40));
+    let args = ["src", "--query", "main rs", "--hidden", "--max-depth=3", "--reverse", "--height", "40%", "/etc"];
+    assert_eq!(parse(&args), Ok(Command::Run(expected)));
+}
+
+#[test]
+fn help_and_version_short_circuit_parsing() {
+    assert_eq!(parse(&["--exact", "-h"]), Ok(Command::Help));
+    assert_eq!(parse(&["--version", "--bogus"]), Ok(Command::Version));
+}
+
+#[test]
+fn invalid_arguments_are_reported() {
+    assert!(parse(&["--bogus"]).is_err());
+    assert!(parse(&["--max-depth"]).is_err());
+    assert!(parse(&["--max-depth", "deep"]).is_err());
+    assert!(parse(&["--min-depth", "3", "--max-depth", "2"]).is_err());
+    assert!(parse(&["--type", "x"]).is_err());
+    assert!(parse(&["--height", "150%"]).is_err());
+    assert!(parse(&["--preview-window", "left"]).is_err());
+    assert!(parse(&["--case", "upper"]).is_err());
+}
+
+#[test]
+fn a_preview_command_opens_the_preview_on_the_right() {
+    let mut expected = Options::new();
+    expected.preview_command = Some(String::from("head {}"));
+    expected.layout.preview = Some(PreviewPosition::Right);
+    assert_eq!(parse(&["--preview", "head {}"]), Ok(Command::Run(expected.clone())));
+    expected.layout.preview = Some(PreviewPosition::Bottom);
+    assert_eq!(parse(&["--preview-window=bottom", "--preview", "head {}"]), Ok(Command::Run(expected)));
+}
+
+#[test]
+fn the_daemon_subcommand_takes_the_usual_options() {
+    let mut expected = Options::new();
+    expected.root_dirs = vec![PathBuf::from("src")];
+    expected.scanner.include_hidden = true;
+    assert_eq!(parse(&["daemon", "--hidden", "src"]), Ok(Command::Daemon(expected.clone())));
+    assert_eq!(parse(&["./daemon"]), Ok(Command::Run(Options { root_dirs: vec![PathBuf::from("./daemon")], ..Options::new() })));
+}
+
+#[test]
+fn types_replace_the_default_and_add_up() {
+    let mut expected = Options::new();
+    expected.scanner.types = EntryTypes { files: false, directories: true, symlinks: true };
+    assert_eq!(parse(&["--type", "d", "-t", "l"]), Ok(Command::Run(expected.clone())));
+    assert_eq!(ScannerOptions::from_key(&expected.scanner.to_key()), Some(expected.scanner));
+}
+
+#[test]
+fn filters_are_parsed_and_metadata_filters_always_scan() {
+    let options = match parse(&["--ext", ".RS,toml", "--glob", "**/*_test.rs", "--exclude=vendor/**"]) {
+        Ok(Command::Run(options)) => options,
+        other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!(options.scanner.filters.extensions, vec!["rs", "toml"]);
+    assert_eq!(ScannerOptions::from_key(&options.scanner.to_key()), Some(options.scanner.clone()));
+    assert!(options.use_daemon);
+    let options = match parse(&["--size", "+1M", "--changed-within", "2d"]) {
+        Ok(Command::Run(options)) => options,
+        other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!(options.scanner.filters.size, Some(SizeLimit::AtLeast(1024 * 1024)));
+    assert_eq!(options.scanner.filters.changed_within, Some(2 * 24 * 60 * 60));
+    assert_eq!((options.use_daemon, options.cache), (false, CacheMode::Off));
+    assert!(parse(&["--size", "1M"]).is_err());
+    assert!(parse(&["--changed-within", "2y"]).is_err());
+}
@@ -0,0 +1,312 @@
+extern crate rustbox;
+
+use rustbox::{RustBox, Color};
+use std::cmp;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use fuzzy::layout::{Layout, Area};
+
+const MAX_PREVIEW_LINES: usize = 1000;
+const MAX_PREVIEW_BYTES: usize = 1024 * 1024;
+const PREVIEW_TIMEOUT_MS: u64 = 3000;
+const TAB: &'static str = "    ";
+
+// The lines to preview for a result: the output of `command` with `{}` replaced by the
+// result, otherwise the start of the file or the entries of the directory. A command is
+// stopped once `cancelled` says the preview isn't wanted anymore.
+pub fn load_preview<F: Fn() -> bool>(result: &OsStr, command: Option<&str>, cancelled: F) -> Vec<String> {
+    let lines = match command {
+        Some(command) => run_command(command, result, cancelled),
+        None => {
+            let path = Path::new(result);
+            if path.is_dir() {
+                list_directory(path)
+            } else {
+                read_file(path)
+            }
+        }
+    };
+    match lines {
+        Ok(lines) => lines.into_iter().take(MAX_PREVIEW_LINES).map(|line| line.replace("\t", TAB)).collect(),
+        Err(error) => vec![error],
+    }
+}
+
+// Previews load on a thread of their own, so a slow command or file never holds up the
+// interface. A load that finishes after another result was highlighted is thrown away.
+pub struct PreviewView {
+    layout: Layout,
+    command: Option<String>,
+    rustbox: Arc<Mutex<RustBox>>,
+    result: Mutex<Option<OsString>>,
+    // counts the results shown, so a load can tell it is still wanted
+    generation: Arc<AtomicUsize>,
+    lines: Arc<Mutex<Vec<String>>>,
+    scroll: Arc<AtomicUsize>,
+}
+
+impl PreviewView {
+
+    pub fn new(layout: Layout, command: Option<String>, rustbox: Arc<Mutex<RustBox>>) -> PreviewView {
+        PreviewView {
+            layout: layout,
+            command: command,
+            rustbox: rustbox,
+            result: Mutex::new(None),
+            generation: Arc::new(AtomicUsize::new(0)),
+            lines: Arc::new(Mutex::new(vec![])),
+            scroll: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    // Only loads and redraws when the result has changed. `rustbox` is the locked screen,
+    // the load draws once it is done by locking it again.
+    pub fn show(&self, rustbox: &RustBox, result: Option<OsString>) {
+        let mut current_result = self.result.lock().unwrap();
+        if *current_result == result {
+            return;
+        }
+        *current_result = result.clone();
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        self.scroll.store(0, Ordering::Relaxed);
+        {
+            let mut lines = self.lines.lock().unwrap();
+            lines.clear();
+            draw(rustbox, self.layout, &lines, 0);
+        }
+        let result = match result {
+            Some(result) => result,
+            None => return,
+        };
+        let (layout, command) = (self.layout, self.command.clone());
+        let (current_generation, lines, scroll, rustbox) = (self.generation.clone(), self.lines.clone(), self.scroll.clone(), self.rustbox.clone());
+        thread::spawn(move || {
+            let is_wanted = || current_generation.load(Ordering::SeqCst) == generation;
+            let loaded = load_preview(&result, command.as_ref().map(|command| &command[..]), || !is_wanted());
+            let rustbox = rustbox.lock().unwrap();
+            // checked with the screen locked, which `show` holds while it moves on
+            if is_wanted() {
+                let mut lines = lines.lock().unwrap();
+                *lines = loaded;
+                draw(&rustbox, layout, &lines, scroll.load(Ordering::Relaxed));
+            }
+        });
+    }
+
+    pub fn scroll_down(&self, rustbox: &RustBox) {
+        let lines = self.lines.lock().unwrap();
+        let scroll = self.scroll.load(Ordering::Relaxed);
+        if scroll + 1 < lines.len() {
+            self.scroll.store(scroll + 1, Ordering::Relaxed);
+            draw(rustbox, self.layout, &lines, scroll + 1);
+        }
+    }
+
+    pub fn scroll_up(&self, rustbox: &RustBox) {
+        let lines = self.lines.lock().unwrap();
+        let scroll = self.scroll.load(Ordering::Relaxed);
+        if scroll > 0 {
+            self.scroll.store(scroll - 1, Ordering::Relaxed);
+            draw(rustbox, self.layout, &lines, scroll - 1);
+        }
+    }
+}
+
+fn draw(rustbox: &RustBox, layout: Layout, lines: &Vec<String>, scroll: usize) {
+    let area = match layout.preview_area(rustbox.width(), rustbox.height()) {
+        Some(area) => area,
+        None => return,
+    };
+    let empty_line: String = (0..area.width).map(|_| ' ').collect();
+    for row in 0..area.height {
+        rustbox.print(area.x, area.y + row, rustbox::RB_NORMAL, Color::White, Color::Black, &empty_line);
+        if let Some(line) = lines.get(scroll + row) {
+            let line: String = line.chars().take(area.width).collect();
+            rustbox.print(area.x, area.y + row, rustbox::RB_NORMAL, Color::White, Color::Black, &line);
+        }
+    }
+    draw_border(rustbox, layout, area, lines.len(), scroll);
+    rustbox.present();
+}
+
+// The border doubles as a position indicator once the preview is scrolled.
+fn draw_border(rustbox: &RustBox, layout: Layout, area: Area, number_of_lines: usize, scroll: usize) {
+    let border_row = if layout.reverse { area.y + area.height } else { area.y.saturating_sub(1) };
+    if area.x > 0 {
+        for row in area.y..(area.y + area.height) {
+            rustbox.print(area.x - 1, row, rustbox::RB_NORMAL, Color::Cyan, Color::Black, "\u{2502}");
+        }
+    } else {
+        let border: String = (0..area.width).map(|_| '\u{2500}').collect();
+        rustbox.print(0, border_row, rustbox::RB_NORMAL, Color::Cyan, Color::Black, &border);
+    }
+    if scroll > 0 {
+        let position = format!(" {}/{} ", scroll + 1, number_of_lines);
+        let column = area.x + area.width.saturating_sub(position.len());
+        let row = if area.x > 0 { area.y } else { border_row };
+        rustbox.print(column, row, rustbox::RB_REVERSE, Color::Cyan, Color::Black, &position);
+    }
+}
+
+// The result is quoted byte for byte, so file names that aren't UTF-8 reach the command intact.
+// Only the start of the output is read, and the command is killed once that is in, the
+// preview isn't wanted anymore or it takes too long.
+fn run_command<F: Fn() -> bool>(command: &str, result: &OsStr, cancelled: F) -> Result<Vec<String>, String> {
+    let quoted = shell_quote(result.as_bytes());
+    let parts: Vec<&[u8]> = command.split("{}").map(|part| part.as_bytes()).collect();
+    let command = OsString::from_vec(parts.join(&quoted[..]));
+    let spawned = Command::new("sh").arg("-c").arg(&command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(error) => return Err(format!("can't run preview command: {}", error)),
+    };
+    let deadline = Instant::now() + Duration::from_millis(PREVIEW_TIMEOUT_MS);
+    // both are read at once, so neither fills up its pipe while the other is waited on
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+    let output = match wait_for_output(&stdout, deadline, &cancelled) {
+        Ok(ref output) if output.is_empty() => wait_for_output(&stderr, deadline, &cancelled),
+        output => output,
+    };
+    let _ = child.kill();
+    let _ = child.wait();
+    output.map(|output| to_lines(&output))
+}
+
+fn read_in_background<R: Read + Send + 'static>(reader: Option<R>) -> Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel();
+    if let Some(reader) = reader {
+        thread::spawn(move || {
+            let _ = tx.send(read_start(reader).unwrap_or(vec![]));
+        });
+    }
+    rx
+}
+
+fn wait_for_output<F: Fn() -> bool>(receiver: &Receiver<Vec<u8>>, deadline: Instant, cancelled: &F) -> Result<Vec<u8>, String> {
+    loop {
+        match receiver.recv_timeout(Duration::from_millis(20)) {
+            Ok(output) => return Ok(output),
+            Err(RecvTimeoutError::Disconnected) => return Ok(vec![]),
+            Err(RecvTimeoutError::Timeout) => {
+                if cancelled() {
+                    return Ok(vec![]);
+                }
+                if Instant::now() >= deadline {
+                    return Err(format!("preview command took longer than {} seconds", PREVIEW_TIMEOUT_MS / 1000));
+                }
+            }
+        }
+    }
+}
+
+// Up to `MAX_PREVIEW_LINES` lines or `MAX_PREVIEW_BYTES` bytes, whichever comes first.
+fn read_start<R: Read>(mut reader: R) -> io::Result<Vec<u8>> {
+    let mut content = vec![];
+    let mut buffer = vec![0; 64 * 1024];
+    let mut number_of_lines = 0;
+    while content.len() < MAX_PREVIEW_BYTES && number_of_lines < MAX_PREVIEW_LINES {
+        let length = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(length) => length,
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        number_of_lines += buffer[..length].iter().filter(|byte| **byte == b'\n').count();
+        content.extend_from_slice(&buffer[..length]);
+    }
+    content.truncate(MAX_PREVIEW_BYTES);
+    Ok(content)
+}
+
+fn list_directory(path: &Path) -> Result<Vec<String>, String> {
+    let entries = try!(fs::read_dir(path).map_err(|error| format!("can't preview: {}", error)));
+    let mut names = vec![];
+    for entry in entries {
+        if let Ok(entry) = entry {
+            let mut name = entry.file_name().to_string_lossy().into_owned();
+            if entry.path().is_dir() {
+                name.push('/');
+            }
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+// Only regular files are opened, a FIFO or a device could block or never end.
+fn read_file(path: &Path) -> Result<Vec<String>, String> {
+    let metadata = try!(fs::metadata(path).map_err(|error| format!("can't preview: {}", error)));
+    if !metadata.is_file() {
+        return Ok(vec![String::from("not a regular file")]);
+    }
+    let file = try!(File::open(path).map_err(|error| format!("can't preview: {}", error)));
+    let content = try!(read_start(file).map_err(|error| format!("can't preview: {}", error)));
+    // the same check git and grep use
+    if content[..cmp::min(content.len(), 8000)].contains(&0) {
+        return Ok(vec![String::from("binary file")]);
+    }
+    Ok(to_lines(&content))
+}
+
+fn to_lines(content: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(content).lines().map(|line| line.to_string()).collect()
+}
+
+fn shell_quote(value: &[u8]) -> Vec<u8> {
+    let mut quoted = vec![b'\''];
+    for byte in value.iter() {
+        match *byte {
+            b'\'' => quoted.extend_from_slice(b"'\\''"),
+            byte => quoted.push(byte),
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+#[test]
+fn directories_are_previewed_as_listings() {
+    assert_eq!(load_preview(OsStr::new("test"), None, || false), vec!["first", "second", "third_dir/"]);
+}
+
+#[test]
+fn binary_files_get_a_placeholder() {
+    use std::env;
+    use std::io::Write;
+    let path = env::temp_dir().join(format!("fuzzy_preview_binary_{}", std::process::id()));
+    File::create(&path).unwrap().write_all(b"\x7fELF\x00\x01\tabc").unwrap();
+    assert_eq!(load_preview(path.as_os_str(), None, || false), vec!["binary file"]);
+    File::create(&path).unwrap().write_all(b"fn main() {\n\tprintln!();\n}\n").unwrap();
+    assert_eq!(load_preview(path.as_os_str(), None, || false), vec!["fn main() {", "    println!();", "}"]);
+    assert_eq!(load_preview(OsStr::new("/dev/zero"), None, || false), vec!["not a regular file"]);
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn preview_commands_get_the_quoted_result() {
+    assert_eq!(load_preview(OsStr::new("it's here"), Some("echo {}"), || false), vec!["it's here"]);
+    assert_eq!(load_preview(&OsString::from_vec(b"caf\xe9".to_vec()), Some("printf %s {} | od -An -tx1"), || false), vec![" 63 61 66 e9"]);
+}
+
+#[test]
+fn preview_commands_stop_at_the_first_lines_or_when_cancelled() {
+    assert_eq!(load_preview(OsStr::new("y"), Some("yes {}"), || false).len(), MAX_PREVIEW_LINES);
+    let started = Instant::now();
+    assert_eq!(load_preview(OsStr::new("x"), Some("sleep 10"), || true), Vec::<String>::new());
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
@@ -0,0 +1,220 @@
+use fuzzy::matcher::{self, Match};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TermKind {
+    Fuzzy,
+    Exact,
+    Prefix,
+    Suffix,
+    Equal,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Term {
+    pub kind: TermKind,
+    pub text: Vec<char>,
+    pub inverse: bool,
+}
+
+// Space separated terms that all have to match, where each group holds the
+// alternatives written with `|` between them:
+//
+//   term     fuzzy match        'term    exact substring (fuzzy with --exact)
+//   ^term    prefix             term$    suffix
+//   !term    must not contain   \        escapes a space
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    pub groups: Vec<Vec<Term>>,
+    pub case_sensitive: bool,
+}
+
+impl Query {
+
+    pub fn parse(query: &str, exact: bool, case_sensitive: bool) -> Result<Query, String> {
+        let tokens = tokenize(query);
+        let mut groups: Vec<Vec<Term>> = vec![];
+        let mut alternative = false;
+        for (index, token) in tokens.iter().enumerate() {
+            if token == "|" {
+                let has_left = index > 0 && tokens[index - 1] != "|";
+                let has_right = index + 1 < tokens.len() && tokens[index + 1] != "|";
+                if !has_left || !has_right {
+                    return Err(String::from("'|' needs a term on both sides"));
+                }
+                alternative = true;
+                continue;
+            }
+            let term = parse_term(token, exact, case_sensitive);
+            match (term, alternative, groups.last_mut()) {
+                (Some(term), true, Some(group)) => group.push(term),
+                (Some(term), _, _) => groups.push(vec![term]),
+                (None, _, _) => {}
+            }
+            alternative = false;
+        }
+        Ok(Query { groups: groups, case_sensitive: case_sensitive })
+    }
+
+    // Every group needs one matching term. The score and positions are the sum of
+    // the best matching term in each group, negated terms only ever filter.
+    pub fn matches(&self, candidate: &[char]) -> Option<Match> {
+        let mut score = 0;
+        let mut positions = vec![];
+        for group in self.groups.iter() {
+            let mut group_matched = false;
+            let mut best: Option<Match> = None;
+            for term in group.iter() {
+                match term.matches(candidate, self.case_sensitive) {
+                    Some(found) => {
+                        group_matched = true;
+                        if best.as_ref().map_or(true, |best| found.score > best.score) {
+                            best = Some(found);
+                        }
+                    }
+                    None => {}
+                }
+            }
+            if !group_matched {
+                return None;
+            }
+            if let Some(best) = best {
+                score += best.score;
+                positions.extend(best.positions);
+            }
+        }
+        positions.sort();
+        positions.dedup();
+        Some(Match { score: score, positions: positions })
+    }
+}
+
+impl Term {
+
+    fn matches(&self, candidate: &[char], case_sensitive: bool) -> Option<Match> {
+        let found = match self.kind {
+            TermKind::Fuzzy => matcher::fuzzy_match(&self.text, candidate, case_sensitive),
+            TermKind::Exact => matcher::substring_match(&self.text, candidate, case_sensitive),
+            TermKind::Prefix => matcher::anchored_match(&self.text, candidate, 0, case_sensitive),
+            TermKind::Suffix => {
+                let start = candidate.len().saturating_sub(self.text.len());
+                matcher::anchored_match(&self.text, candidate, start, case_sensitive)
+            }
+            TermKind::Equal if candidate.len() == self.text.len() => matcher::anchored_match(&self.text, candidate, 0, case_sensitive),
+            TermKind::Equal => None,
+        };
+        match (found, self.inverse) {
+            (Some(_), true) => None,
+            (None, true) => Some(Match { score: 0, positions: vec![] }),
+            (found, false) => found,
+        }
+    }
+}
+
+// Splits on spaces, except the ones escaped with a backslash.
+fn tokenize(query: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut characters = query.chars().peekable();
+    while let Some(character) = characters.next() {
+        match character {
+            '\\' if characters.peek() == Some(&' ') => {
+                token.push(' ');
+                characters.next();
+            }
+            ' ' => {
+                if !token.is_empty() {
+                    tokens.push(token.clone());
+                    token.clear();
+                }
+            }
+            _ => token.push(character),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+// Terms left empty once their operators are stripped match everything, so they are dropped.
+fn parse_term(token: &str, exact: bool, case_sensitive: bool) -> Option<Term> {
+    let mut text = token;
+    let inverse = text.starts_with("!");
+    if inverse {
+        text = &text[1..];
+    }
+    let mut kind = if exact { TermKind::Exact } else { TermKind::Fuzzy };
+    if text.starts_with("'") {
+        kind = if exact { TermKind::Fuzzy } else { TermKind::Exact };
+        text = &text[1..];
+    } else {
+        let prefix = text.starts_with("^");
+        if prefix {
+            text = &text[1..];
+        }
+        let suffix = text.len() > 0 && text.ends_with("$");
+        if suffix {
+            text = &text[..(text.len() - 1)];
+        }
+        kind = match (prefix, suffix) {
+            (true, true) => TermKind::Equal,
+            (true, false) => TermKind::Prefix,
+            (false, true) => TermKind::Suffix,
+            (false, false) => kind,
+        };
+    }
+    // like fzf, a negated fuzzy term would exclude far too much
+    if inverse && kind == TermKind::Fuzzy {
+        kind = TermKind::Exact;
+    }
+    if text.is_empty() {
+        return None;
+    }
+    let text = if case_sensitive { text.chars().collect() } else { text.chars().map(matcher::lowercase).collect() };
+    Some(Term { kind: kind, text: text, inverse: inverse })
+}
+
+#[cfg(test)]
+fn term(kind: TermKind, text: &str, inverse: bool) -> Term {
+    Term { kind: kind, text: text.chars().collect(), inverse: inverse }
+}
+
+#[test]
+fn terms_and_operators_are_parsed() {
+    let query = Query::parse("src 'view ^lib rs$ !test a\\ b ^main$", false, false).unwrap();
+    assert_eq!(query.groups, vec![
+        vec![term(TermKind::Fuzzy, "src", false)],
+        vec![term(TermKind::Exact, "view", false)],
+        vec![term(TermKind::Prefix, "lib", false)],
+        vec![term(TermKind::Suffix, "rs", false)],
+        vec![term(TermKind::Exact, "test", true)],
+        vec![term(TermKind::Fuzzy, "a b", false)],
+        vec![term(TermKind::Equal, "main", false)],
+    ]);
+    let query = Query::parse("'view main", true, false).unwrap();
+    assert_eq!(query.groups, vec![vec![term(TermKind::Fuzzy, "view", false)], vec![term(TermKind::Exact, "main", false)]]);
+}
+
+#[test]
+fn alternatives_are_grouped_and_must_have_two_sides() {
+    let query = Query::parse("rs$ | toml$ src", false, false).unwrap();
+    assert_eq!(query.groups, vec![
+        vec![term(TermKind::Suffix, "rs", false), term(TermKind::Suffix, "toml", false)],
+        vec![term(TermKind::Fuzzy, "src", false)],
+    ]);
+    assert!(Query::parse("| src", false, false).is_err());
+    assert!(Query::parse("src |", false, false).is_err());
+    assert!(Query::parse("src | | lib", false, false).is_err());
+}
+
+#[test]
+fn every_group_has_to_match() {
+    let query = Query::parse("src rs$ | toml$ !test", false, false).unwrap();
+    let matches = |candidate: &str| query.matches(&candidate.chars().collect::<Vec<char>>()).is_some();
+    assert!(matches("src/lib.rs"));
+    assert!(matches("src/Cargo.toml"));
+    assert!(!matches("src/README.md"));
+    assert!(!matches("src/test/lib.rs"));
+    let found = query.matches(&"src/lib.rs".chars().collect::<Vec<char>>()).unwrap();
+    assert_eq!(found.positions, vec![0, 1, 2, 8, 9]);
+}
@@ -1,49 +1,205 @@
 use crossbeam;
-use regex::Regex;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::mem;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::sync::mpsc::channel;
+use fuzzy::directory_scanner::Links;
+use fuzzy::matcher::{self, Matcher};
+
+// `path` is what is shown and matched, with replacement characters for anything that
+// isn't valid UTF-8, and `output` the exact path to print when that isn't `path`.
+// `positions` are the indices of the characters the query matched, for highlighting.
+// `hidden` is set for a file that is or is below a hidden entry of its root.
+// `label_length` is how many characters `path` starts with for the label of its root.
+// `link` is where a symbolic link points, or what a result found below a followed one is
+// really, and whether nothing is there.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RankedResult {
+    pub score: i32,
+    pub path: String,
+    pub positions: Vec<usize>,
+    pub output: Option<OsString>,
+    pub hidden: bool,
+    pub label_length: usize,
+    pub link: Option<(String, bool)>,
+}
+
+impl RankedResult {
+
+    pub fn new(path: OsString) -> RankedResult {
+        match path.into_string() {
+            Ok(path) => RankedResult { score: 0, path: path, positions: vec![], output: None, hidden: false, label_length: 0, link: None },
+            Err(output) => RankedResult { score: 0, path: output.to_string_lossy().into_owned(), positions: vec![], output: Some(output), hidden: false, label_length: 0, link: None },
+        }
+    }
+
+    // The path exactly as it was found, to print.
+    pub fn output_path(&self) -> OsString {
+        match self.output {
+            Some(ref output) => output.clone(),
+            None => OsString::from(self.path.clone()),
+        }
+    }
+
+    // Like comparing `output_path`, without copying it.
+    pub fn has_output_path(&self, path: &OsStr) -> bool {
+        match self.output {
+            Some(ref output) => output == path,
+            None => OsStr::new(&self.path) == path,
+        }
+    }
+}
+
+// A directory results are found under. They are shown relative to `root_dir` under `label`,
+// and printed under `output_root`, the directory as it was given. Both are empty for the
+// current directory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanRoot {
+    pub root_dir: PathBuf,
+    pub label: String,
+    pub output_root: PathBuf,
+}
+
+impl ScanRoot {
+
+    pub fn new(root_dir: PathBuf, label: String, output_root: PathBuf) -> ScanRoot {
+        ScanRoot { root_dir: root_dir, label: label, output_root: output_root }
+    }
+
+    // Lines read from stdin are shown and printed as they are.
+    pub fn none() -> ScanRoot {
+        ScanRoot::new(PathBuf::new(), String::new(), PathBuf::new())
+    }
+}
 
 pub struct ResultSet {
-    results: Vec<String>,
-    filtered_results: Vec<String>,
-    filter_string: String,
-    filter_applied: bool
+    results: Vec<RankedResult>,
+    filtered_results: Vec<RankedResult>,
+    filter: Option<Matcher>,
+    boosts: HashMap<String, i32>,
+    show_hidden: bool,
+    // hidden results set aside while they aren't shown, to bring back without a scan
+    hidden_results: Vec<RankedResult>,
+    // the paths `results` and `hidden_results` were sent as, so nothing is added twice, not
+    // even when roots overlap
+    paths: HashSet<OsString>,
+    // the symbolic links scans into this set came across
+    links: Arc<Mutex<Links>>,
 }
 
 impl ResultSet {
 
     pub fn new() -> ResultSet {
-        ResultSet { results: vec![], filter_string: String::from("*"), filter_applied: false, filtered_results: vec![] }
+        ResultSet { results: vec![], filter: None, filtered_results: vec![], boosts: HashMap::new(), show_hidden: true, hidden_results: vec![], paths: HashSet::new(), links: Arc::new(Mutex::new(HashMap::new())) }
     }
 
-    pub fn add_many(&mut self, results: Vec<String>, root_dir: &str) {
-        let mut new = vec![];
-        for result in results {
-            let mut sanitized_string = result.clone();
-            if root_dir != "/" {
-                sanitized_string = result.replace(root_dir, "")[1..].to_string();
-            }
-            new.push(sanitized_string);
+    // Extra score for results picked before, see `FrecencyDatabase::boosts`.
+    pub fn set_boosts(&mut self, boosts: HashMap<String, i32>) {
+        self.boosts = boosts;
+    }
+
+    // Where scans record the symbolic links they come across, to be shown with the results
+    // they sent after.
+    pub fn links(&self) -> Arc<Mutex<Links>> {
+        self.links.clone()
+    }
+
+    // Results are shown relative to their root, under its label when it has one. Results
+    // that are already there are left out, whichever root they were found under.
+    pub fn add_many(&mut self, results: Vec<OsString>, root: &ScanRoot) {
+        let new = self.linked_results(results, root, false);
+        self.add_unseen(new);
+    }
+
+    // Like `add_many` for only the hidden files among `results`, when the others are there already.
+    pub fn add_hidden(&mut self, results: Vec<OsString>, root: &ScanRoot) {
+        let new = self.linked_results(results, root, true);
+        self.add_unseen(new);
+    }
+
+    // Hidden results that are hidden again are kept aside, so showing them is instant.
+    pub fn set_show_hidden(&mut self, show_hidden: bool) {
+        if self.show_hidden == show_hidden {
+            return;
         }
-        self.results.extend(new);
-        if self.filter_applied {
-            self.re_run_filter()
+        self.show_hidden = show_hidden;
+        if show_hidden {
+            let hidden_results = mem::replace(&mut self.hidden_results, vec![]);
+            self.add_displayed(hidden_results);
+        } else {
+            let (hidden_results, results) = mem::replace(&mut self.results, vec![]).into_iter().partition(|result| result.hidden);
+            self.results = results;
+            self.hidden_results = hidden_results;
+            self.filtered_results.retain(|result| !result.hidden);
         }
     }
 
+    // Removes a file, or a directory and everything below it, from the results and the
+    // filtered results alike. Removing the root directory itself removes everything found under it.
+    pub fn remove(&mut self, path: &OsStr, root: &ScanRoot) {
+        let removed = displayed_result(path, root).path;
+        let is_removed = |result: &RankedResult| {
+            let result = &result.path;
+            if removed.is_empty() || removed.ends_with('/') {
+                result.starts_with(&removed)
+            } else {
+                *result == removed || (result.starts_with(&removed) && result[removed.len()..].starts_with('/'))
+            }
+        };
+        self.results.retain(|result| !is_removed(result));
+        self.hidden_results.retain(|result| !is_removed(result));
+        self.filtered_results.retain(|result| !is_removed(result));
+        let path = path.as_bytes();
+        self.paths.retain(|sent| {
+            let sent = sent.as_bytes();
+            !(sent.starts_with(path) && (sent.len() == path.len() || path.ends_with(b"/") || sent[path.len()] == b'/'))
+        });
+    }
+
+    #[cfg(test)]
     pub fn to_vec(&self) -> Vec<String> {
-        if self.filter_applied {
+        if self.filter.is_some() {
+            self.filtered_results.iter().map(|result| result.path.clone()).collect()
+        } else {
+            self.results.iter().map(|result| result.path.clone()).collect()
+        }
+    }
+
+    pub fn to_ranked_vec(&self) -> Vec<RankedResult> {
+        if self.filter.is_some() {
             self.filtered_results.clone()
         } else {
-            self.results.clone()
+            let mut ranked: Vec<RankedResult> = self.results.iter().map(|result| {
+                RankedResult { score: boost(&self.boosts, &result.path), ..result.clone() }
+            }).collect();
+            if !self.boosts.is_empty() {
+                // stable, so everything else stays in the order it was found
+                ranked.sort_by(|a, b| b.score.cmp(&a.score));
+            }
+            ranked
         }
     }
 
-    pub fn apply_filter(&mut self, regex: Regex) -> Vec<String> {
-        if self.filter_applied && self.additive_filter(&regex) {
-            self.apply_to_filtered(regex)
+    pub fn apply_filter(&mut self, filter: Matcher) -> Vec<RankedResult> {
+        if filter.is_empty() {
+            self.filter = None;
+            self.filtered_results.clear();
         } else {
-            self.apply_to_all(regex)
+            let refines_current_filter = match self.filter {
+                Some(ref current_filter) => filter.refines(current_filter),
+                None => false,
+            };
+            if refines_current_filter {
+                self.apply_to_filtered(filter)
+            } else {
+                self.apply_to_all(filter)
+            }
         }
+        self.to_ranked_vec()
     }
 
     pub fn number_of_results(&self) -> usize {
@@ -52,53 +208,179 @@ impl ResultSet {
 
     // ------ private methods ----------//
 
-    fn re_run_filter(&mut self) {
-        let string = self.filter_string.clone();
-        self.apply_filter(Regex::new(&string).unwrap());
+    // The results with the paths they were sent as.
+    fn linked_results(&self, results: Vec<OsString>, root: &ScanRoot, only_hidden: bool) -> Vec<(OsString, RankedResult)> {
+        let links = self.links.lock().unwrap();
+        results.into_iter().filter_map(|result| {
+            let mut displayed = displayed_result(&result, root);
+            if only_hidden && !displayed.hidden {
+                return None;
+            }
+            if !links.is_empty() {
+                displayed.link = link(&links, Path::new(&result), &root.root_dir);
+            }
+            Some((result, displayed))
+        }).collect()
     }
 
-    fn additive_filter(&self, regex: &Regex) -> bool {
-        String::from(regex.as_str()).contains(&self.filter_string)
+    fn add_unseen(&mut self, new: Vec<(OsString, RankedResult)>) {
+        let new = {
+            let paths = &mut self.paths;
+            new.into_iter().filter(|&(ref sent, _)| paths.insert(sent.clone())).map(|(_, result)| result).collect()
+        };
+        self.add_displayed(new);
     }
 
-    fn apply_to_filtered(&mut self, regex: Regex) -> Vec<String> {
-        self.filtered_results  = filter_collection(&mut self.filtered_results, &regex);
-        self.filter_string = regex.to_string();
-        self.filter_applied = true;
-        self.filtered_results.clone()
+    fn add_displayed(&mut self, new: Vec<RankedResult>) {
+        let new = if self.show_hidden {
+            new
+        } else {
+            let (hidden, new): (Vec<RankedResult>, Vec<RankedResult>) = new.into_iter().partition(|result| result.hidden);
+            self.hidden_results.extend(hidden);
+            new
+        };
+        if let Some(ref filter) = self.filter {
+            let mut matches = filter_collection(&new, filter, &self.boosts);
+            sort_ranked(&mut matches);
+            let filtered_results = mem::replace(&mut self.filtered_results, vec![]);
+            self.filtered_results = merge_ranked(filtered_results, matches);
+        }
+        self.results.extend(new);
     }
 
-    fn apply_to_all(&mut self, regex: Regex) -> Vec<String> {
-        self.filtered_results = filter_collection(&mut self.results, &regex);
-        self.filter_string = regex.to_string();
-        self.filter_applied = true;
-        self.filtered_results.clone()
+    fn apply_to_filtered(&mut self, filter: Matcher) {
+        self.filtered_results = filter_collection(&self.filtered_results, &filter, &self.boosts);
+        sort_ranked(&mut self.filtered_results);
+        self.filter = Some(filter);
+    }
+
+    fn apply_to_all(&mut self, filter: Matcher) {
+        self.filtered_results = filter_collection(&self.results, &filter, &self.boosts);
+        sort_ranked(&mut self.filtered_results);
+        self.filter = Some(filter);
     }
 }
 
 impl Clone for ResultSet {
 
     fn clone(&self) -> ResultSet {
-        ResultSet { results: self.to_vec(), filter_string: String::from("*"), filter_applied: self.filter_applied, filtered_results: self.filtered_results.clone() }
+        ResultSet {
+            results: self.results.clone(),
+            filter: self.filter.clone(),
+            filtered_results: self.filtered_results.clone(),
+            boosts: self.boosts.clone(),
+            show_hidden: self.show_hidden,
+            hidden_results: self.hidden_results.clone(),
+            paths: self.paths.clone(),
+            links: self.links.clone(),
+        }
+    }
+}
+
+// How a scanned path is shown and printed, see `add_many`.
+fn displayed_result(result: &OsStr, root: &ScanRoot) -> RankedResult {
+    let path = Path::new(result);
+    let root_dir = &root.root_dir;
+    let relative = if root_dir.as_os_str().is_empty() { path } else { path.strip_prefix(root_dir).unwrap_or(path) };
+    // directories keep the `/` they were sent with
+    let is_directory = result.as_bytes().ends_with(b"/");
+    let displayed = under_prefix(OsStr::new(&root.label), relative, is_directory);
+    let output = under_prefix(root.output_root.as_os_str(), relative, is_directory);
+    let mut result = RankedResult::new(displayed);
+    if result.output_path() != output {
+        result.output = Some(output);
+    }
+    if !root.label.is_empty() {
+        result.label_length = root.label.trim_right_matches('/').chars().count() + 1;
+    }
+    // lines read from stdin have no root and nothing hidden
+    result.hidden = !root_dir.as_os_str().is_empty() && relative.components().any(|component| match component {
+        Component::Normal(name) => name.as_bytes().starts_with(b"."),
+        _ => false,
+    });
+    result
+}
+
+// The link `path` is, or the one it was found below on the way from `root_dir`, which then
+// points to a directory that is there.
+fn link(links: &Links, path: &Path, root_dir: &Path) -> Option<(String, bool)> {
+    let mut below = PathBuf::new();
+    let mut current = path;
+    while current.starts_with(root_dir) && current != root_dir {
+        if let Some(&(ref target, dangling)) = links.get(current) {
+            let target = if below.as_os_str().is_empty() { target.clone() } else { target.join(&below) };
+            return Some((target.to_string_lossy().into_owned(), dangling));
+        }
+        below = match current.file_name() {
+            Some(name) if below.as_os_str().is_empty() => PathBuf::from(name),
+            Some(name) => Path::new(name).join(&below),
+            None => return None,
+        };
+        current = match current.parent() {
+            Some(parent) => parent,
+            None => return None,
+        };
+    }
+    None
+}
+
+fn under_prefix(prefix: &OsStr, relative: &Path, is_directory: bool) -> OsString {
+    let mut path = OsString::new();
+    if !prefix.is_empty() {
+        let prefix = prefix.as_bytes();
+        let length = prefix.iter().rposition(|byte| *byte != b'/').map_or(0, |index| index + 1);
+        path.push(OsStr::from_bytes(&prefix[..length]));
+        path.push("/");
+    }
+    path.push(relative);
+    if is_directory && !path.as_bytes().ends_with(b"/") {
+        path.push("/");
+    }
+    path
+}
+
+fn sort_ranked(results: &mut Vec<RankedResult>) {
+    results.sort_by(|a, b| matcher::compare_ranked(a.score, &a.path, b.score, &b.path));
+}
+
+// Both lists are sorted already, so a batch of new matches doesn't sort everything again.
+fn merge_ranked(sorted: Vec<RankedResult>, new: Vec<RankedResult>) -> Vec<RankedResult> {
+    let mut merged = Vec::with_capacity(sorted.len() + new.len());
+    let mut sorted = sorted.into_iter().peekable();
+    for result in new {
+        while sorted.peek().map_or(false, |earlier| matcher::compare_ranked(earlier.score, &earlier.path, result.score, &result.path) != cmp::Ordering::Greater) {
+            merged.push(sorted.next().unwrap());
+        }
+        merged.push(result);
     }
+    merged.extend(sorted);
+    merged
+}
+
+fn boost(boosts: &HashMap<String, i32>, path: &str) -> i32 {
+    boosts.get(path).cloned().unwrap_or(0)
 }
 
-fn filter_collection(collection: &Vec<String>, regex: &Regex) -> Vec<String> {
+fn filter_collection(collection: &Vec<RankedResult>, filter: &Matcher, boosts: &HashMap<String, i32>) -> Vec<RankedResult> {
         let mut matched_results = vec![];
         let mut receivers = vec![];
 
         crossbeam::scope(|scope| {
             let filter_concurrency_limit = 8;
-            let chunk_length = collection.len() / filter_concurrency_limit;
+            let chunk_length = cmp::max(collection.len() / filter_concurrency_limit, 1);
             for chunk in collection.chunks(chunk_length) {
                 let (tx, rx) = channel();
                 receivers.push(rx);
-                let local_regex = regex.clone();
+                let local_filter = filter.clone();
                 scope.spawn(move || {
                     let mut local_matches = vec![];
                     for content in chunk.iter() {
-                        if local_regex.is_match(content) {
-                            local_matches.push(content.clone());
+                        if let Some(found) = local_filter.matches(&content.path) {
+                            local_matches.push(RankedResult {
+                                score: found.score + boost(boosts, &content.path),
+                                positions: found.positions,
+                                ..content.clone()
+                            });
                         }
                     }
                     let _ = tx.send(local_matches);
@@ -112,3 +394,157 @@ fn filter_collection(collection: &Vec<String>, regex: &Regex) -> Vec<String> {
         }
         matched_results
 }
+
+#[cfg(test)]
+fn fuzzy(query: &str) -> Matcher {
+    Matcher::extended(query, false, matcher::CaseMode::Smart).unwrap()
+}
+
+#[cfg(test)]
+fn os_strings(paths: &[&str]) -> Vec<OsString> {
+    paths.iter().map(|path| OsString::from(path)).collect()
+}
+
+#[test]
+fn filtered_results_carry_the_matched_positions() {
+    let mut result_set = ResultSet::new();
+    result_set.add_many(os_strings(&["/root/src/lib.rs", "/root/README.md"]), &ScanRoot::new(PathBuf::from("/root"), String::new(), PathBuf::new()));
+    let results = result_set.apply_filter(fuzzy("lib"));
+    assert_eq!(results, vec![RankedResult { score: results[0].score, path: String::from("src/lib.rs"), positions: vec![4, 5, 6], output: None, hidden: false, label_length: 0, link: None }]);
+}
+
+#[test]
+fn matches_added_while_filtering_are_merged_in_rank_order() {
+    let mut result_set = ResultSet::new();
+    result_set.apply_filter(fuzzy("mod"));
+    result_set.add_many(os_strings(&["src/a/b/c/mod.rs", "mod.rs", "README.md"]), &ScanRoot::none());
+    result_set.add_many(os_strings(&["src/mod.rs", "src/a/b/c/d/mod.rs", "modules.rs"]), &ScanRoot::none());
+    let merged = result_set.to_ranked_vec();
+    let mut sorted = merged.clone();
+    sort_ranked(&mut sorted);
+    assert_eq!(merged.len(), 5);
+    assert_eq!(merged, sorted);
+}
+
+#[test]
+fn boosted_results_rank_first_among_equal_matches() {
+    let mut result_set = ResultSet::new();
+    let mut boosts = HashMap::new();
+    boosts.insert(String::from("src/b/mod.rs"), 8);
+    result_set.set_boosts(boosts);
+    result_set.add_many(os_strings(&["src/a/mod.rs", "src/b/mod.rs", "README.md"]), &ScanRoot::none());
+    let paths = |results: Vec<RankedResult>| results.into_iter().map(|result| result.path).collect::<Vec<String>>();
+    assert_eq!(paths(result_set.to_ranked_vec()), vec!["src/b/mod.rs", "src/a/mod.rs", "README.md"]);
+    assert_eq!(paths(result_set.apply_filter(fuzzy("mod"))), vec!["src/b/mod.rs", "src/a/mod.rs"]);
+}
+
+#[test]
+fn removed_files_and_directories_leave_the_filtered_results() {
+    let mut result_set = ResultSet::new();
+    let root = ScanRoot::new(PathBuf::from("/root"), String::from("project"), PathBuf::from("project"));
+    result_set.add_many(os_strings(&["/root/src/lib.rs", "/root/src/libs/mod.rs", "/root/lib.rs"]), &root);
+    result_set.add_many(os_strings(&["/root/lib.rs", "/root/src/main.rs"]), &root);
+    assert_eq!(result_set.number_of_results(), 4);
+    result_set.apply_filter(fuzzy("rs"));
+    result_set.remove(OsStr::new("/root/src/lib"), &root);
+    assert_eq!(result_set.number_of_results(), 4);
+    result_set.remove(OsStr::new("/root/src/libs"), &root);
+    result_set.remove(OsStr::new("/root/lib.rs"), &root);
+    assert_eq!(result_set.to_vec().len(), 2);
+    result_set.remove(OsStr::new("/root"), &root);
+    assert_eq!(result_set.number_of_results(), 0);
+}
+
+#[test]
+fn paths_that_are_not_utf8_are_matched_lossily_and_kept_exactly() {
+    let mut result_set = ResultSet::new();
+    let latin1 = OsStr::from_bytes(b"/root/caf\xe9.txt").to_os_string();
+    result_set.add_many(vec![latin1], &ScanRoot::new(PathBuf::from("/root"), String::from("src"), PathBuf::from("src/")));
+    let results = result_set.apply_filter(fuzzy("caf"));
+    assert_eq!(results[0].path, "src/caf\u{fffd}.txt");
+    assert_eq!(results[0].output_path().as_bytes(), b"src/caf\xe9.txt");
+}
+
+#[test]
+fn hidden_results_are_set_aside_and_brought_back() {
+    let mut result_set = ResultSet::new();
+    let root = ScanRoot::new(PathBuf::from("/root/.config"), String::new(), PathBuf::new());
+    result_set.add_many(os_strings(&["/root/.config/src/lib.rs", "/root/.config/.github/ci.yml"]), &root);
+    result_set.apply_filter(fuzzy("i"));
+    result_set.set_show_hidden(false);
+    let paths = |results: Vec<RankedResult>| results.into_iter().map(|result| result.path).collect::<Vec<String>>();
+    assert_eq!(paths(result_set.to_ranked_vec()), vec!["src/lib.rs"]);
+    result_set.add_hidden(os_strings(&["/root/.config/src/lib.rs", "/root/.config/.github/ci.yml", "/root/.config/.env"]), &root);
+    assert_eq!(result_set.number_of_results(), 1);
+    result_set.set_show_hidden(true);
+    assert_eq!(paths(result_set.to_ranked_vec()), vec!["src/lib.rs", ".github/ci.yml"]);
+    assert_eq!(result_set.number_of_results(), 3);
+}
+
+#[test]
+fn results_are_shown_under_the_root_label_and_printed_under_the_root() {
+    let mut result_set = ResultSet::new();
+    result_set.add_many(os_strings(&["/home/me/.config/nvim/", "/home/me/.config/nvim/init.lua"]), &ScanRoot::new(PathBuf::from("/home/me/.config"), String::from(".config"), PathBuf::from("/home/me/.config")));
+    result_set.add_many(os_strings(&["/work/fuzzy/src/init.rs"]), &ScanRoot::new(PathBuf::from("/work/fuzzy"), String::from("fuzzy"), PathBuf::from("../fuzzy/")));
+    let results = result_set.apply_filter(fuzzy("init"));
+    let paths: Vec<(String, OsString)> = results.iter().map(|result| (result.path.clone(), result.output_path())).collect();
+    assert_eq!(paths, vec![
+        (String::from("fuzzy/src/init.rs"), OsString::from("../fuzzy/src/init.rs")),
+        (String::from(".config/nvim/init.lua"), OsString::from("/home/me/.config/nvim/init.lua")),
+    ]);
+    assert_eq!(results[1].label_length, ".config/".len());
+    result_set.apply_filter(fuzzy(""));
+    assert_eq!(result_set.to_vec()[0], ".config/nvim/");
+}
+
+#[test]
+fn results_are_added_once_until_removed() {
+    let mut result_set = ResultSet::new();
+    let root = ScanRoot::new(PathBuf::from("/root"), String::new(), PathBuf::new());
+    result_set.set_show_hidden(false);
+    result_set.add_many(os_strings(&["/root/lib.rs", "/root/lib.rs", "/root/.env"]), &root);
+    result_set.add_many(os_strings(&["/root/lib.rs", "/root/.env"]), &root);
+    result_set.add_hidden(os_strings(&["/root/.env"]), &root);
+    assert_eq!(result_set.number_of_results(), 1);
+    result_set.set_show_hidden(true);
+    assert_eq!(result_set.to_vec(), vec!["lib.rs", ".env"]);
+    result_set.remove(OsStr::new("/root/lib.rs"), &root);
+    result_set.add_many(os_strings(&["/root/lib.rs"]), &root);
+    assert_eq!(result_set.to_vec(), vec![".env", "lib.rs"]);
+}
+
+#[test]
+fn files_below_overlapping_roots_are_listed_under_the_first_that_sends_them() {
+    let mut result_set = ResultSet::new();
+    let project = ScanRoot::new(PathBuf::from("/work/project"), String::from("project"), PathBuf::from("project"));
+    let src = ScanRoot::new(PathBuf::from("/work/project/src"), String::from("src"), PathBuf::from("project/src"));
+    result_set.add_many(os_strings(&["/work/project/src/", "/work/project/src/lib.rs", "/work/project/README.md"]), &project);
+    result_set.add_many(os_strings(&["/work/project/src/lib.rs", "/work/project/src/main.rs"]), &src);
+    assert_eq!(result_set.to_vec(), vec!["project/src/", "project/src/lib.rs", "project/README.md", "src/main.rs"]);
+    result_set.remove(OsStr::new("/work/project/src"), &src);
+    result_set.remove(OsStr::new("/work/project/src"), &project);
+    assert_eq!(result_set.to_vec(), vec!["project/README.md"]);
+    result_set.add_many(os_strings(&["/work/project/src/lib.rs"]), &src);
+    assert_eq!(result_set.to_vec(), vec!["project/README.md", "src/lib.rs"]);
+}
+
+#[test]
+fn results_below_a_followed_link_show_where_they_really_are() {
+    let mut result_set = ResultSet::new();
+    let root = ScanRoot::new(PathBuf::from("/root"), String::new(), PathBuf::new());
+    {
+        let links = result_set.links();
+        let mut links = links.lock().unwrap();
+        links.insert(PathBuf::from("/root/linked"), (PathBuf::from("../shared"), false));
+        links.insert(PathBuf::from("/root/dangling"), (PathBuf::from("missing"), true));
+        links.insert(PathBuf::from("/elsewhere"), (PathBuf::from("/root"), false));
+    }
+    result_set.add_many(os_strings(&["/root/linked/", "/root/linked/nvim/init.lua", "/root/dangling", "/root/lib.rs"]), &root);
+    let links: Vec<(String, Option<(String, bool)>)> = result_set.to_ranked_vec().into_iter().map(|result| (result.path, result.link)).collect();
+    assert_eq!(links, vec![
+        (String::from("linked/"), Some((String::from("../shared"), false))),
+        (String::from("linked/nvim/init.lua"), Some((String::from("../shared/nvim/init.lua"), false))),
+        (String::from("dangling"), Some((String::from("missing"), true))),
+        (String::from("lib.rs"), None),
+    ]);
+}
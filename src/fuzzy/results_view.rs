@@ -3,107 +3,272 @@ extern crate time;
 extern crate clipboard;
 
 use rustbox::{RustBox, Color};
+use std::cmp;
+use std::ffi::OsString;
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use fuzzy::layout::{self, Layout};
+use fuzzy::result_set::RankedResult;
 
 pub struct ResultsView {
-    results: Mutex<Vec<String>>,
+    results: Mutex<Vec<RankedResult>>,
+    marked_results: Mutex<Vec<OsString>>,
     highlighted_result_row: AtomicUsize,
     number_of_results: AtomicUsize,
+    status_width: AtomicUsize,
+    offset: AtomicUsize,
+    layout: Layout,
 }
 
 impl ResultsView {
 
-    pub fn new() -> ResultsView {
+    pub fn new(layout: Layout) -> ResultsView {
         ResultsView { 
             results: Mutex::new(vec![]),
+            marked_results: Mutex::new(vec![]),
             highlighted_result_row: AtomicUsize::new(0),
             number_of_results: AtomicUsize::new(0),
+            status_width: AtomicUsize::new(0),
+            offset: AtomicUsize::new(0),
+            layout: layout,
         }
     }
 
-    pub fn update(&self, rustbox: Arc<Mutex<RustBox>>, results: Vec<String>) {
-        self.highlighted_result_row.store(0, Ordering::Relaxed);
-        self.clear(rustbox.clone());
+    // The highlighted result stays highlighted as files come and go, wherever it moves to.
+    // Only `reset_highlight` goes back to the top.
+    pub fn update(&self, rustbox: Arc<Mutex<RustBox>>, results: Vec<RankedResult>) {
         let rustbox = rustbox.lock().unwrap();
-        let max_displayed_results;
-        if results.len() > rustbox.height() {
-            max_displayed_results = rustbox.height();
-        } else {
-            max_displayed_results = results.len();
-        }
-        // clean old status bar
-        let mut empty_string = String::new();
-        for _ in 1..self.number_of_results.load(Ordering::Relaxed).to_string().len() {
-            empty_string = empty_string.clone() + " ";
-        }
-        let x_value = rustbox.width() - self.number_of_results.load(Ordering::Relaxed).to_string().len();
-        rustbox.print(x_value, 0, rustbox::RB_NORMAL, Color::White, Color::Black, &empty_string);
-
-        // new status bar
-        let x_value = rustbox.width() - results.len().to_string().len();
-        rustbox.print(x_value, 0, rustbox::RB_NORMAL, Color::White, Color::Black, &results.len().to_string());
         self.number_of_results.store(results.len(), Ordering::Relaxed);
+        self.draw_status_bar(&rustbox);
 
-        for index in 0..max_displayed_results {
-            rustbox.print(0, index + 1, rustbox::RB_NORMAL, Color::White, Color::Black, &results[index]);
-        }
-        rustbox.present();
         let mut locked_results = self.results.lock().unwrap();
+        let highlighted = match self.highlighted_result_row.load(Ordering::Relaxed) {
+            0 => None,
+            row => locked_results.get(row - 1).map(|result| result.output_path()),
+        };
+        let row = highlighted_row(&results, highlighted);
+        self.highlighted_result_row.store(row, Ordering::Relaxed);
+        let offset = if row > 0 {
+            layout::scroll_offset(row - 1, self.offset.load(Ordering::Relaxed), self.layout.max_results(rustbox.height()))
+        } else {
+            0
+        };
+        self.offset.store(offset, Ordering::Relaxed);
         locked_results.clear();
         locked_results.extend(results);
+        self.draw_rows(&rustbox, &locked_results);
+        rustbox.present();
+    }
+
+    // For a new query, so the best match is at the top again once its results arrive.
+    pub fn reset_highlight(&self) {
+        self.highlighted_result_row.store(0, Ordering::Relaxed);
+        self.offset.store(0, Ordering::Relaxed);
     }
 
     pub fn highlight_next(&self, rustbox: &RustBox) {
-        let results = self.results.lock().unwrap();
-        // unhighlight the current row
-        if self.highlighted_result_row.load(Ordering::Relaxed) > 0 {
-            rustbox.print(0, self.highlighted_result_row.load(Ordering::Relaxed), rustbox::RB_NORMAL, Color::White, Color::Black, &results[(self.highlighted_result_row.load(Ordering::Relaxed) - 1)]);
+        let number_of_results = self.results.lock().unwrap().len();
+        let row = self.highlighted_result_row.load(Ordering::Relaxed);
+        if row < number_of_results {
+            self.highlight_row(rustbox, row + 1);
+        } else if self.layout.cycle && number_of_results > 0 {
+            self.highlight_row(rustbox, 1);
         }
-        // highlight next row
-        self.highlighted_result_row.fetch_add(1, Ordering::Relaxed);
-        rustbox.print(0, self.highlighted_result_row.load(Ordering::Relaxed), rustbox::RB_NORMAL, Color::Magenta, Color::Black, &results[(self.highlighted_result_row.load(Ordering::Relaxed) - 1)]);
-        rustbox.present();
     }
 
+    // Moving up from the first result goes back to the prompt, unless the list cycles.
     pub fn highlight_previous(&self, rustbox: &RustBox) {
+        let number_of_results = self.results.lock().unwrap().len();
+        match self.highlighted_result_row.load(Ordering::Relaxed) {
+            0 | 1 if self.layout.cycle && number_of_results > 0 => self.highlight_row(rustbox, number_of_results),
+            0 => {}
+            row => self.highlight_row(rustbox, row - 1),
+        }
+    }
+
+    pub fn highlight_next_page(&self, rustbox: &RustBox) {
+        let number_of_results = self.results.lock().unwrap().len();
+        let row = self.highlighted_result_row.load(Ordering::Relaxed);
+        if number_of_results > 0 {
+            let page = cmp::max(self.layout.max_results(rustbox.height()), 1);
+            self.highlight_row(rustbox, cmp::min(row + page, number_of_results));
+        }
+    }
+
+    pub fn highlight_previous_page(&self, rustbox: &RustBox) {
+        let row = self.highlighted_result_row.load(Ordering::Relaxed);
+        if row > 0 {
+            let page = cmp::max(self.layout.max_results(rustbox.height()), 1);
+            self.highlight_row(rustbox, cmp::max(row.saturating_sub(page), 1));
+        }
+    }
+
+    pub fn highlight_first(&self, rustbox: &RustBox) {
+        if !self.results.lock().unwrap().is_empty() {
+            self.highlight_row(rustbox, 1);
+        }
+    }
+
+    pub fn highlight_last(&self, rustbox: &RustBox) {
+        let number_of_results = self.results.lock().unwrap().len();
+        if number_of_results > 0 {
+            self.highlight_row(rustbox, number_of_results);
+        }
+    }
+
+    // Marks are kept by path so they survive the query changing underneath them.
+    pub fn toggle_mark(&self, rustbox: &RustBox) {
+        if self.results.lock().unwrap().is_empty() {
+            return;
+        }
+        if !self.has_highlighted_result() {
+            self.highlight_next(rustbox);
+        }
         let results = self.results.lock().unwrap();
-        // unhighlight the current row
-        if self.highlighted_result_row.load(Ordering::Relaxed) > 0 {
-            rustbox.print(0, self.highlighted_result_row.load(Ordering::Relaxed), rustbox::RB_NORMAL, Color::White, Color::Black, &results[(self.highlighted_result_row.load(Ordering::Relaxed) - 1)]);
-            if self.highlighted_result_row.load(Ordering::Relaxed) > 1 {
-                // hightlight the previous row
-                self.highlighted_result_row.fetch_sub(1, Ordering::Relaxed);
-                rustbox.print(0, self.highlighted_result_row.load(Ordering::Relaxed), rustbox::RB_NORMAL, Color::Magenta, Color::Black, &results[(self.highlighted_result_row.load(Ordering::Relaxed) - 1)]);
-            } else {
-                self.highlighted_result_row.store(0, Ordering::Relaxed)
+        let index = self.highlighted_result_row.load(Ordering::Relaxed) - 1;
+        {
+            let mut marked_results = self.marked_results.lock().unwrap();
+            let path = results[index].output_path();
+            match marked_results.iter().position(|marked| *marked == path) {
+                Some(position) => { marked_results.remove(position); }
+                None => marked_results.push(path),
             }
-            rustbox.present();
         }
+        self.draw_row(rustbox, &results, index, true);
+        self.draw_status_bar(rustbox);
+        rustbox.present();
+    }
+
+    pub fn marked_results(&self) -> Vec<OsString> {
+        self.marked_results.lock().unwrap().clone()
+    }
+
+    pub fn status_width(&self) -> usize {
+        self.status_width.load(Ordering::Relaxed)
+    }
+
+    pub fn first_result(&self) -> Option<OsString> {
+        self.results.lock().unwrap().first().map(|result| result.output_path())
     }
 
     pub fn has_highlighted_result(&self) -> bool {
         self.highlighted_result_row.load(Ordering::Relaxed) > 0
     }
 
-    pub fn get_highlighted(&self) -> String {
+    pub fn get_highlighted(&self) -> OsString {
         let index = self.highlighted_result_row.load(Ordering::Relaxed);
         index.to_string();
-        self.results.lock().unwrap()[index - 1].clone()
+        self.results.lock().unwrap()[index - 1].output_path()
     }
 
     // -------- private methods ---------- //
+
+    // `row` is 1-based like `highlighted_result_row`, 0 highlights nothing.
+    fn highlight_row(&self, rustbox: &RustBox, row: usize) {
+        let results = self.results.lock().unwrap();
+        self.highlighted_result_row.store(row, Ordering::Relaxed);
+        if row > 0 {
+            let visible = self.layout.max_results(rustbox.height());
+            let offset = layout::scroll_offset(row - 1, self.offset.load(Ordering::Relaxed), visible);
+            self.offset.store(offset, Ordering::Relaxed);
+        }
+        self.draw_rows(rustbox, &results);
+        rustbox.present();
+    }
+
+    fn draw_rows(&self, rustbox: &RustBox, results: &Vec<RankedResult>) {
+        self.clear(rustbox);
+        let visible = self.layout.max_results(rustbox.height());
+        let offset = self.offset.load(Ordering::Relaxed);
+        let highlighted_row = self.highlighted_result_row.load(Ordering::Relaxed);
+        for index in offset..cmp::min(offset + visible, results.len()) {
+            self.draw_row(rustbox, results, index, index + 1 == highlighted_row);
+        }
+        self.draw_scrollbar(rustbox, results.len());
+    }
+
+    // A marker column, then the result itself with the label of its root in yellow, the characters
+    // the query matched in bold green, and where it points for a symbolic link or below a followed one,
+    // in red when that is gone.
+    fn draw_row(&self, rustbox: &RustBox, results: &Vec<RankedResult>, index: usize, highlighted: bool) {
+        let row = self.layout.result_row(index - self.offset.load(Ordering::Relaxed), rustbox.height());
+        let result = &results[index];
+        let marker = if self.marked_results.lock().unwrap().contains(&result.output_path()) { ">" } else { " " };
+        let color = if highlighted { Color::Magenta } else { Color::White };
+        rustbox.print(0, row, rustbox::RB_BOLD, Color::Cyan, Color::Black, marker);
+        // leave the last column for the scrollbar
+        let path: String = result.path.chars().take(self.width(rustbox).saturating_sub(3)).collect();
+        rustbox.print(2, row, rustbox::RB_NORMAL, color, Color::Black, &path);
+        let label: String = path.chars().take(result.label_length).collect();
+        rustbox.print(2, row, rustbox::RB_NORMAL, Color::Yellow, Color::Black, &label);
+        let style = if highlighted { rustbox::RB_BOLD | rustbox::RB_UNDERLINE } else { rustbox::RB_BOLD };
+        for (column, character) in path.chars().enumerate() {
+            if result.positions.binary_search(&column).is_ok() {
+                rustbox.print_char(2 + column, row, style, Color::Green, Color::Black, character);
+            }
+        }
+        if let Some((ref target, dangling)) = result.link {
+            let column = 2 + path.chars().count();
+            let target: String = format!(" -> {}", target).chars().take(self.width(rustbox).saturating_sub(column + 1)).collect();
+            let color = if dangling { Color::Red } else { Color::Blue };
+            rustbox.print(column, row, rustbox::RB_NORMAL, color, Color::Black, &target);
+        }
+    }
+
+    fn draw_scrollbar(&self, rustbox: &RustBox, total: usize) {
+        let visible = self.layout.max_results(rustbox.height());
+        let column = self.width(rustbox).saturating_sub(1);
+        if let Some((start, size)) = layout::scrollbar(total, self.offset.load(Ordering::Relaxed), visible) {
+            for index in 0..visible {
+                let thumb = if index >= start && index < start + size { "\u{2588}" } else { "\u{2502}" };
+                rustbox.print(column, self.layout.result_row(index, rustbox.height()), rustbox::RB_NORMAL, Color::Cyan, Color::Black, thumb);
+            }
+        }
+    }
+
+    fn draw_status_bar(&self, rustbox: &RustBox) {
+        let prompt_row = self.layout.prompt_row(rustbox.height());
+        // clean old status bar
+        let old_width = self.status_width.load(Ordering::Relaxed);
+        let empty_string: String = (0..old_width).map(|_| ' ').collect();
+        rustbox.print(self.width(rustbox).saturating_sub(old_width), prompt_row, rustbox::RB_NORMAL, Color::White, Color::Black, &empty_string);
+
+        // new status bar
+        let number_of_marked_results = self.marked_results.lock().unwrap().len();
+        let status = if number_of_marked_results > 0 {
+            format!("{} marked  {}", number_of_marked_results, self.number_of_results.load(Ordering::Relaxed))
+        } else {
+            self.number_of_results.load(Ordering::Relaxed).to_string()
+        };
+        rustbox.print(self.width(rustbox).saturating_sub(status.len()), prompt_row, rustbox::RB_NORMAL, Color::White, Color::Black, &status);
+        self.status_width.store(status.len(), Ordering::Relaxed);
+    }
     
-    fn clear(&self, rustbox: Arc<Mutex<RustBox>>) {
-        let rustbox = rustbox.lock().unwrap();
+    fn width(&self, rustbox: &RustBox) -> usize {
+        self.layout.results_width(rustbox.width())
+    }
+
+    fn clear(&self, rustbox: &RustBox) {
         // clear all result rows
-        let mut empty_line = String::new(); // TODO there must be a better way of doing this in rust
-        for _ in 1..(rustbox.width() + 1) {
-            empty_line = empty_line.clone() + " ";
-        }
-        for row in 1..rustbox.height() {
-            rustbox.print(0, row, rustbox::RB_NORMAL, Color::White, Color::Black, &empty_line);
+        let empty_line: String = (0..self.width(rustbox)).map(|_| ' ').collect();
+        for index in 0..self.layout.max_results(rustbox.height()) {
+            rustbox.print(0, self.layout.result_row(index, rustbox.height()), rustbox::RB_NORMAL, Color::White, Color::Black, &empty_line);
         }
     }
 }
 
+// The row `highlighted` is on among `results`, 1-based, or 0 when it is gone or there was none.
+fn highlighted_row(results: &Vec<RankedResult>, highlighted: Option<OsString>) -> usize {
+    match highlighted {
+        Some(path) => results.iter().position(|result| result.has_output_path(&path)).map_or(0, |index| index + 1),
+        None => 0,
+    }
+}
+
+#[test]
+fn the_highlighted_result_is_followed_to_its_new_row() {
+    let results: Vec<RankedResult> = ["src/new.rs", "src/lib.rs", "README.md"].iter().map(|path| RankedResult::new(OsString::from(path))).collect();
+    assert_eq!(highlighted_row(&results, Some(OsString::from("src/lib.rs"))), 2);
+    assert_eq!(highlighted_row(&results, Some(OsString::from("src/gone.rs"))), 0);
+    assert_eq!(highlighted_row(&results, None), 0);
+}
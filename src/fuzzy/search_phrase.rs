@@ -1,81 +1,91 @@
-extern crate regex;
-
-use regex::Regex;
 use std::sync::Arc;
 use fuzzy::event_service::EventService;
+use fuzzy::matcher::{Matcher, CaseMode};
 
 pub struct SearchPhrase {
     pub content: String,
+    pub regex_mode: bool,
+    pub exact: bool,
+    pub case: CaseMode,
+    // not part of the query, but sent along with it so results follow right away
+    pub include_hidden: bool,
     event_service: Arc<EventService>
 }
 
 impl SearchPhrase {
 
-    pub fn init(event_service: Arc<EventService>) -> SearchPhrase {
-        SearchPhrase { 
-            content: String::new(),
-            event_service: event_service
-        }
+    pub fn from_string(string: String, event_service: Arc<EventService>) -> SearchPhrase {
+        SearchPhrase { content: string, regex_mode: false, exact: false, case: CaseMode::Smart, include_hidden: false, event_service: event_service }
     }
 
-    pub fn from_string(string: String, event_service: Arc<EventService>) -> SearchPhrase {
-        SearchPhrase { content: string, event_service: event_service }
+    pub fn replace(&mut self, string: String) {
+        self.content = string;
+        self.event_service.trigger_search_phrase_changed(self.clone());
+    }
+
+    pub fn toggle_regex_mode(&mut self) {
+        self.regex_mode = !self.regex_mode;
+        self.event_service.trigger_search_phrase_changed(self.clone());
     }
 
-    pub fn update(&mut self, string: String)  {
-        self.content = self.content.clone() + &string[..];
+    pub fn cycle_case_mode(&mut self) {
+        self.case = self.case.next();
         self.event_service.trigger_search_phrase_changed(self.clone());
     }
 
-    pub fn delete_last(&mut self)  {
-        let mut new_string = self.content.clone();
-        new_string.pop();
-        self.content = new_string;
+    pub fn toggle_hidden(&mut self) {
+        self.include_hidden = !self.include_hidden;
         self.event_service.trigger_search_phrase_changed(self.clone());
     }
 
-    pub fn to_regex(&self) -> Regex {
-        let mut regex_phrase = String::from("(?i)");
-        for character in self.content.chars() {
-            regex_phrase.push_str(".*");
-            regex_phrase.push(character);
+    pub fn to_matcher(&self) -> Result<Matcher, String> {
+        if self.regex_mode {
+            Matcher::regex(&self.content, self.case)
+        } else {
+            Matcher::extended(&self.content, self.exact, self.case)
         }
-        regex_phrase.push_str(".*");
-        Regex::new(&regex_phrase).unwrap()
     }
 }
 
 impl Clone for SearchPhrase {
 
     fn clone(&self) -> Self {
-        SearchPhrase::from_string(self.content.clone(), self.event_service.clone())
+        let mut search_phrase = SearchPhrase::from_string(self.content.clone(), self.event_service.clone());
+        search_phrase.regex_mode = self.regex_mode;
+        search_phrase.exact = self.exact;
+        search_phrase.case = self.case;
+        search_phrase.include_hidden = self.include_hidden;
+        search_phrase
     }
 }
 
 #[test]
 fn by_default_it_has_no_content() {
-    let search_phrase = SearchPhrase::init();
+    let search_phrase = SearchPhrase::from_string(String::new(), Arc::new(EventService::new()));
     assert_eq!(search_phrase.content, "");
+    assert!(!search_phrase.regex_mode && !search_phrase.exact);
+    assert_eq!(search_phrase.case, CaseMode::Smart);
 }
 
 #[test]
 fn a_search_phrase_can_be_created_from_string() {
-    let search_phrase = SearchPhrase::from_string("Hello there");
+    let search_phrase = SearchPhrase::from_string("Hello there".to_string(), Arc::new(EventService::new()));
     assert_eq!(search_phrase.content, "Hello there");
 }
 
 #[test]
 fn after_creation_the_content_can_be_updated() {
-    let mut search_phrase = SearchPhrase::from_string("Hello");
-    search_phrase.update(" there".to_string());
+    let mut search_phrase = SearchPhrase::from_string("Hello".to_string(), Arc::new(EventService::new()));
+    search_phrase.replace("Hello there".to_string());
     assert_eq!(search_phrase.content, "Hello there");
 }
 
 #[test]
 fn a_one_change_observer_can_be_added() {
-    let mut search_phrase = SearchPhrase::from_string("Hello");
-    search_phrase.on_change(Box::new(|string: String| {
-        println!("Hello to you");
-    }));
-    search_phrase.update(" there".to_string())
+    let event_service = Arc::new(EventService::new());
+    let mut search_phrase = SearchPhrase::from_string("Hello".to_string(), event_service.clone());
+    search_phrase.replace("Help".to_string());
+    let changes = event_service.search_phrases.lock().unwrap().export();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].content, "Help");
 }
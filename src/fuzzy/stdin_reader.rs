@@ -0,0 +1,77 @@
+use std::ffi::OsString;
+use std::io::{self, BufRead};
+use std::os::unix::ffi::OsStringExt;
+use std::os::raw::c_int;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const MAX_BATCH_SIZE: usize = 1000;
+
+extern "C" {
+    fn isatty(fd: c_int) -> c_int;
+}
+
+// The interface never reads stdin, termbox talks to /dev/tty directly.
+pub fn stdin_is_piped() -> bool {
+    unsafe { isatty(0) == 0 }
+}
+
+pub struct StdinReader {
+    subscriber: Arc<Mutex<Sender<Vec<OsString>>>>,
+}
+
+impl StdinReader {
+
+    pub fn new(subscriber: Arc<Mutex<Sender<Vec<OsString>>>>) -> StdinReader {
+        StdinReader { subscriber: subscriber }
+    }
+
+    // Lines are sent on as soon as they arrive, batched up while stdin is faster than we are.
+    pub fn read(&self) {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            let mut stdin = stdin.lock();
+            let mut line = vec![];
+            loop {
+                line.clear();
+                match stdin.read_until(b'\n', &mut line) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {
+                        let line = trim_line_ending(&line);
+                        if !line.is_empty() && tx.send(OsString::from_vec(line.to_vec())).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        loop {
+            let mut batch = match rx.recv() {
+                Ok(line) => vec![line],
+                Err(_) => break,
+            };
+            while batch.len() < MAX_BATCH_SIZE {
+                match rx.try_recv() {
+                    Ok(line) => batch.push(line),
+                    Err(_) => break,
+                }
+            }
+            if self.subscriber.lock().unwrap().send(batch).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+fn trim_line_ending(line: &[u8]) -> &[u8] {
+    let mut end = line.len();
+    if end > 0 && line[end - 1] == b'\n' {
+        end -= 1;
+    }
+    if end > 0 && line[end - 1] == b'\r' {
+        end -= 1;
+    }
+    &line[..end]
+}
@@ -4,27 +4,54 @@ extern crate clipboard;
 
 use rustbox::{RustBox, Key, Color};
 use self::clipboard::ClipboardContext;
+use std::cmp;
 use std::error::Error;
+use std::ffi::OsString;
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::{Ordering, AtomicBool};
+use std::sync::atomic::{Ordering, AtomicBool, AtomicUsize};
 use fuzzy::results_view::ResultsView;
+use fuzzy::preview::PreviewView;
 use fuzzy::search_phrase::SearchPhrase;
 use fuzzy::event_service::EventService;
-use std::thread;
+use fuzzy::layout::Layout;
+use fuzzy::line_editor::LineEditor;
+use fuzzy::history::History;
+use fuzzy::matcher::CaseMode;
+use fuzzy::result_set::RankedResult;
 use std::sync::mpsc::{Sender};
 use std::sync::mpsc;
 
+// How long to wait for the rest of an escape sequence before taking Esc on its own.
+const ESCAPE_SEQUENCE_TIMEOUT_MS: i64 = 25;
+
+enum EscapeSequence {
+    Escape,
+    BackTab,
+    Alt(char),
+    Unknown,
+}
+
 pub struct Terminal {
     pub rustbox: Arc<Mutex<RustBox>>,
     pub tx: Arc<Mutex<Sender<Vec<String>>>>,
     event_service: Arc<EventService>,
     search_complete: AtomicBool,
+    aborted: AtomicBool,
+    layout: Layout,
     results_view: ResultsView,
+    preview: Option<PreviewView>,
+    query_length: AtomicUsize,
+    // the query and how it's matched, drawn as the mode indicators
+    search_phrase: Arc<Mutex<SearchPhrase>>,
+    // lines read from stdin are never hidden, so there's nothing to toggle
+    has_hidden_files: bool,
+    filter_error: Mutex<Option<String>>,
+    warning: Mutex<Option<String>>,
 }
 
 impl Terminal {
 
-    pub fn new(event_service: Arc<EventService>) -> Arc<Terminal> {
+    pub fn new(event_service: Arc<EventService>, layout: Layout, preview_command: Option<String>, search_phrase: Arc<Mutex<SearchPhrase>>, has_hidden_files: bool) -> Arc<Terminal> {
         let rustbox = match RustBox::init(Default::default()) {
             Result::Ok(v) => Arc::new(Mutex::new(v)),
             Result::Err(e) => panic!("{}", e),
@@ -32,11 +59,22 @@ impl Terminal {
         let (tx, _) = mpsc::channel();
         Arc::new(
             Terminal{
-                rustbox: rustbox,
+                rustbox: rustbox.clone(),
                 event_service: event_service,
                 tx: Arc::new(Mutex::new(tx)),
                 search_complete: AtomicBool::new(false),
-                results_view: ResultsView::new(),
+                aborted: AtomicBool::new(false),
+                layout: layout,
+                results_view: ResultsView::new(layout),
+                preview: match layout.preview {
+                    Some(_) => Some(PreviewView::new(layout, preview_command, rustbox.clone())),
+                    None => None,
+                },
+                query_length: AtomicUsize::new(0),
+                search_phrase: search_phrase,
+                has_hidden_files: has_hidden_files,
+                filter_error: Mutex::new(None),
+                warning: Mutex::new(None),
             }
         )
     }
@@ -56,8 +94,10 @@ impl Terminal {
         }
     }
 
-    pub fn on_stdin(&self, search_phrase: Arc<Mutex<SearchPhrase>>) {
-        let mut character_index = 0;
+    pub fn on_stdin(&self, history: Arc<Mutex<History>>) {
+        let search_phrase = self.search_phrase.clone();
+        let mut editor = LineEditor::new(&search_phrase.lock().unwrap().content);
+        self.show_initial_query(&editor);
         let mut done = false;
         while !done {
             {
@@ -66,61 +106,147 @@ impl Terminal {
                 match rustbox.peek_event(time::Duration::microseconds(1), false) {
                     Ok(rustbox::Event::KeyEvent(key)) => {
                         match key {
-                            Some(Key::Ctrl('c')) => { break; }
-                            Some(Key::Char(c)) => { 
-                                rustbox.print(character_index, 0, rustbox::RB_REVERSE, Color::White, Color::Black, &c.to_string());
-                                rustbox.present();
-                                character_index = character_index + 1;
-
-                                // have to do this as a new thread but don't want to 
-                                let local_search_phrase = search_phrase.clone();
-                                // do we have to check to make sure this thread is killed properly
-                                thread::spawn(move || {
-                                    let mut local_search_phrase = local_search_phrase.lock().unwrap();
-                                    local_search_phrase.update(c.to_string()); 
-                                });
+                            Some(Key::Ctrl('c')) => {
+                                self.aborted.store(true, Ordering::Relaxed);
+                                break;
+                            }
+                            Some(Key::Esc) => {
+                                match self.read_escape_sequence(&rustbox) {
+                                    EscapeSequence::Escape => {
+                                        self.aborted.store(true, Ordering::Relaxed);
+                                        break;
+                                    }
+                                    EscapeSequence::BackTab => {
+                                        self.results_view.toggle_mark(&rustbox);
+                                        self.move_up(&rustbox);
+                                    }
+                                    EscapeSequence::Alt('b') => {
+                                        editor.move_word_backward();
+                                        self.draw_query(&rustbox, &editor);
+                                    }
+                                    EscapeSequence::Alt('f') => {
+                                        editor.move_word_forward();
+                                        self.draw_query(&rustbox, &editor);
+                                    }
+                                    EscapeSequence::Alt('y') => {
+                                        let changed = editor.yank();
+                                        self.edit_query(&rustbox, &editor, &search_phrase, changed);
+                                    }
+                                    EscapeSequence::Alt('c') => {
+                                        self.results_view.reset_highlight();
+                                        search_phrase.lock().unwrap().cycle_case_mode();
+                                        self.draw_prompt_status(&rustbox);
+                                        rustbox.present();
+                                    }
+                                    EscapeSequence::Alt('h') if self.has_hidden_files => {
+                                        self.results_view.reset_highlight();
+                                        search_phrase.lock().unwrap().toggle_hidden();
+                                        self.draw_prompt_status(&rustbox);
+                                        rustbox.present();
+                                    }
+                                    EscapeSequence::Alt('j') => self.scroll_preview_down(&rustbox),
+                                    EscapeSequence::Alt('k') => self.scroll_preview_up(&rustbox),
+                                    EscapeSequence::Alt(_) | EscapeSequence::Unknown => {}
+                                }
+                            }
+                            Some(Key::Tab) => {
+                                self.results_view.toggle_mark(&rustbox);
+                                self.move_down(&rustbox);
+                            }
+                            Some(Key::Char(c)) => {
+                                let changed = editor.insert(c);
+                                self.edit_query(&rustbox, &editor, &search_phrase, changed);
                             }
                             Some(Key::Backspace) => {
-                                let index: usize;
-                                if character_index != 0 {
-                                    index =  character_index - 1;
-                                } else {
-                                    index =  character_index;
+                                let changed = editor.delete_backward();
+                                self.edit_query(&rustbox, &editor, &search_phrase, changed);
+                            }
+                            Some(Key::Delete) => {
+                                let changed = editor.delete_forward();
+                                self.edit_query(&rustbox, &editor, &search_phrase, changed);
+                            }
+                            Some(Key::Ctrl('w')) => {
+                                let changed = editor.delete_word_backward();
+                                self.edit_query(&rustbox, &editor, &search_phrase, changed);
+                            }
+                            Some(Key::Ctrl('u')) => {
+                                let changed = editor.kill_to_start();
+                                self.edit_query(&rustbox, &editor, &search_phrase, changed);
+                            }
+                            Some(Key::Ctrl('k')) => {
+                                let changed = editor.kill_to_end();
+                                self.edit_query(&rustbox, &editor, &search_phrase, changed);
+                            }
+                            Some(Key::Ctrl('p')) => {
+                                let previous_query = history.lock().unwrap().previous(&editor.content());
+                                if let Some(query) = previous_query {
+                                    let changed = editor.replace(&query);
+                                    self.edit_query(&rustbox, &editor, &search_phrase, changed);
                                 }
-                                rustbox.print(index, 0, rustbox::RB_NORMAL, Color::White, Color::Black, " ");
-                                rustbox.present();
-                                if character_index != 0 {
-                                    character_index = character_index - 1;
+                            }
+                            Some(Key::Ctrl('n')) => {
+                                let next_query = history.lock().unwrap().next();
+                                if let Some(query) = next_query {
+                                    let changed = editor.replace(&query);
+                                    self.edit_query(&rustbox, &editor, &search_phrase, changed);
                                 }
-
-                                // have to do this as a new thread
-                                let local_search_phrase = search_phrase.clone();
-                                // do we have to make sure this thread is killed properly?
-                                thread::spawn(move || {
-                                    let mut local_search_phrase = local_search_phrase.lock().unwrap();
-                                    local_search_phrase.delete_last();
-                                });
                             }
-                            Some(Key::Ctrl('j')) => {
-                                self.results_view.highlight_next(&rustbox);
+                            Some(Key::Left) => {
+                                editor.move_left();
+                                self.draw_query(&rustbox, &editor);
                             }
-                            Some(Key::Ctrl('k')) => {
-                                self.results_view.highlight_previous(&rustbox);
+                            Some(Key::Right) => {
+                                editor.move_right();
+                                self.draw_query(&rustbox, &editor);
+                            }
+                            Some(Key::Ctrl('a')) => {
+                                editor.move_to_start();
+                                self.draw_query(&rustbox, &editor);
+                            }
+                            Some(Key::Ctrl('e')) => {
+                                editor.move_to_end();
+                                self.draw_query(&rustbox, &editor);
+                            }
+                            Some(Key::Ctrl('r')) => {
+                                self.results_view.reset_highlight();
+                                search_phrase.lock().unwrap().toggle_regex_mode();
+                                self.draw_prompt_status(&rustbox);
+                                rustbox.present();
+                            }
+                            Some(Key::Ctrl('j')) => {
+                                self.move_down(&rustbox);
                             }
                             Some(Key::Ctrl('y')) => {
-                                let mut ctx = ClipboardContext::new().unwrap();
-                                let _ = ctx.set_contents(self.results_view.get_highlighted());
-                                done = true;
+                                let results = self.get_selected_results();
+                                if !results.is_empty() {
+                                    let mut ctx = ClipboardContext::new().unwrap();
+                                    let results: Vec<String> = results.iter().map(|result| result.to_string_lossy().into_owned()).collect();
+                                    let _ = ctx.set_contents(results.join("\n"));
+                                    done = true;
+                                }
                             }
                             Some(Key::Down) => {
-                                self.results_view.highlight_next(&rustbox);
+                                self.move_down(&rustbox);
                             }
                             Some(Key::Up) => {
-                                self.results_view.highlight_previous(&rustbox);
+                                self.move_up(&rustbox);
+                            }
+                            Some(Key::PageDown) => {
+                                self.move_page_down(&rustbox);
+                            }
+                            Some(Key::PageUp) => {
+                                self.move_page_up(&rustbox);
+                            }
+                            Some(Key::Home) => {
+                                self.results_view.highlight_first(&rustbox);
+                            }
+                            Some(Key::End) => {
+                                self.results_view.highlight_last(&rustbox);
                             }
                             Some(Key::Enter) => { done = true; }
                             _ => {  }
                         }
+                        self.update_preview(&rustbox);
                     },
                     Err(e) => panic!("{}", e.description()),
                     _ => {  }
@@ -131,15 +257,193 @@ impl Terminal {
         self.event_service.file_finder_condvar.notify_all();
     }
 
-    pub fn show_results(&self, results: Vec<String>) {
+    pub fn show_results(&self, results: Vec<RankedResult>) {
         self.results_view.update(self.rustbox.clone(), results);
+        let rustbox = self.rustbox.lock().unwrap();
+        self.update_preview(&rustbox);
+    }
+
+    pub fn show_filter_error(&self, error: Option<String>) {
+        *self.filter_error.lock().unwrap() = error;
+        let rustbox = self.rustbox.lock().unwrap();
+        self.draw_prompt_status(&rustbox);
+        rustbox.present();
+    }
+
+    // Something the user should know about that doesn't stop them searching.
+    pub fn show_warning(&self, warning: String) {
+        *self.warning.lock().unwrap() = Some(warning);
+        let rustbox = self.rustbox.lock().unwrap();
+        self.draw_prompt_status(&rustbox);
+        rustbox.present();
     }
 
     pub fn has_highlighted_result(&self) -> bool {
         self.results_view.has_highlighted_result()
     }
 
-    pub fn get_highlighted_result(&self) -> String {
+    pub fn get_highlighted_result(&self) -> OsString {
         self.results_view.get_highlighted()
     }
+
+    // The highlighted result, or the best match when nothing has been highlighted.
+    pub fn get_selected_result(&self) -> Option<OsString> {
+        if self.has_highlighted_result() {
+            Some(self.get_highlighted_result())
+        } else {
+            self.results_view.first_result()
+        }
+    }
+
+    // Every marked result in the order they were marked, otherwise the selected result.
+    pub fn get_selected_results(&self) -> Vec<OsString> {
+        let marked_results = self.results_view.marked_results();
+        if !marked_results.is_empty() {
+            return marked_results;
+        }
+        self.get_selected_result().into_iter().collect()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.search_complete.load(Ordering::Relaxed)
+    }
+
+    pub fn was_aborted(&self) -> bool {
+        self.aborted.load(Ordering::Relaxed)
+    }
+
+    // -------- private methods ---------- //
+
+    fn prompt_row(&self, rustbox: &RustBox) -> usize {
+        self.layout.prompt_row(rustbox.height())
+    }
+
+    // Moving away from the prompt goes to worse matches, whichever way up the layout is.
+    fn move_down(&self, rustbox: &RustBox) {
+        if self.layout.reverse {
+            self.results_view.highlight_previous(rustbox);
+        } else {
+            self.results_view.highlight_next(rustbox);
+        }
+    }
+
+    fn move_up(&self, rustbox: &RustBox) {
+        if self.layout.reverse {
+            self.results_view.highlight_next(rustbox);
+        } else {
+            self.results_view.highlight_previous(rustbox);
+        }
+    }
+
+    fn update_preview(&self, rustbox: &RustBox) {
+        if let Some(ref preview) = self.preview {
+            preview.show(rustbox, self.get_selected_result());
+        }
+    }
+
+    fn scroll_preview_down(&self, rustbox: &RustBox) {
+        if let Some(ref preview) = self.preview {
+            preview.scroll_down(rustbox);
+        }
+    }
+
+    fn scroll_preview_up(&self, rustbox: &RustBox) {
+        if let Some(ref preview) = self.preview {
+            preview.scroll_up(rustbox);
+        }
+    }
+
+    fn move_page_down(&self, rustbox: &RustBox) {
+        if self.layout.reverse {
+            self.results_view.highlight_previous_page(rustbox);
+        } else {
+            self.results_view.highlight_next_page(rustbox);
+        }
+    }
+
+    fn move_page_up(&self, rustbox: &RustBox) {
+        if self.layout.reverse {
+            self.results_view.highlight_next_page(rustbox);
+        } else {
+            self.results_view.highlight_previous_page(rustbox);
+        }
+    }
+
+    // Terminals send shift + tab as `Esc [ Z` and alt + key as `Esc key`.
+    fn read_escape_sequence(&self, rustbox: &RustBox) -> EscapeSequence {
+        let timeout = time::Duration::milliseconds(ESCAPE_SEQUENCE_TIMEOUT_MS);
+        match rustbox.peek_event(timeout, false) {
+            Ok(rustbox::Event::KeyEvent(Some(Key::Char('[')))) => {
+                match rustbox.peek_event(timeout, false) {
+                    Ok(rustbox::Event::KeyEvent(Some(Key::Char('Z')))) => EscapeSequence::BackTab,
+                    _ => EscapeSequence::Unknown,
+                }
+            }
+            Ok(rustbox::Event::KeyEvent(Some(Key::Char(c)))) => EscapeSequence::Alt(c),
+            Ok(rustbox::Event::NoEvent) => EscapeSequence::Escape,
+            _ => EscapeSequence::Unknown,
+        }
+    }
+
+    fn show_initial_query(&self, editor: &LineEditor) {
+        let rustbox = self.rustbox.lock().unwrap();
+        self.draw_query(&rustbox, editor);
+    }
+
+    fn edit_query(&self, rustbox: &RustBox, editor: &LineEditor, search_phrase: &Arc<Mutex<SearchPhrase>>, changed: bool) {
+        self.draw_query(rustbox, editor);
+        if changed {
+            self.results_view.reset_highlight();
+            search_phrase.lock().unwrap().replace(editor.content());
+        }
+    }
+
+    fn draw_query(&self, rustbox: &RustBox, editor: &LineEditor) {
+        let prompt_row = self.prompt_row(rustbox);
+        let old_length = self.query_length.load(Ordering::Relaxed);
+        let blank: String = (0..old_length).map(|_| ' ').collect();
+        rustbox.print(0, prompt_row, rustbox::RB_NORMAL, Color::White, Color::Black, &blank);
+        rustbox.print(0, prompt_row, rustbox::RB_REVERSE, Color::White, Color::Black, &editor.content());
+        self.query_length.store(editor.len(), Ordering::Relaxed);
+        self.draw_prompt_status(rustbox);
+        rustbox.set_cursor(editor.cursor() as isize, prompt_row as isize);
+        rustbox.present();
+    }
+
+    fn mode_indicators(&self) -> String {
+        let search_phrase = self.search_phrase.lock().unwrap();
+        let mut indicators = vec![];
+        if search_phrase.regex_mode {
+            indicators.push("[regex]");
+        }
+        match search_phrase.case {
+            CaseMode::Sensitive => indicators.push("[case]"),
+            CaseMode::Insensitive => indicators.push("[no case]"),
+            CaseMode::Smart => {}
+        }
+        if search_phrase.include_hidden && self.has_hidden_files {
+            indicators.push("[hidden]");
+        }
+        indicators.join(" ")
+    }
+
+    // The space between the query and the result count shows the matching mode, and any warning,
+    // or why the query can't be used.
+    fn draw_prompt_status(&self, rustbox: &RustBox) {
+        let start = self.query_length.load(Ordering::Relaxed) + 1;
+        let end = self.layout.results_width(rustbox.width()).saturating_sub(cmp::max(self.results_view.status_width() + 1, 8));
+        if start >= end {
+            return;
+        }
+        let blank: String = (start..end).map(|_| ' ').collect();
+        rustbox.print(start, self.prompt_row(rustbox), rustbox::RB_NORMAL, Color::White, Color::Black, &blank);
+        let filter_error = self.filter_error.lock().unwrap();
+        let (status, color) = match (&*filter_error, &*self.warning.lock().unwrap()) {
+            (&Some(ref error), _) => (error.clone(), Color::Red),
+            (&None, &Some(ref warning)) => (format!("{} {}", self.mode_indicators(), warning).trim_left().to_string(), Color::Yellow),
+            (&None, &None) => (self.mode_indicators(), Color::Cyan),
+        };
+        let status: String = status.chars().take(end - start).collect();
+        rustbox.print(start, self.prompt_row(rustbox), rustbox::RB_NORMAL, color, Color::Black, &status);
+    }
 }
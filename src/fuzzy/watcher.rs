@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::raw::c_int;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use fuzzy::directory_scanner::{self, DirectoryScanner, EntryKind, Links};
+use fuzzy::entry_filter::EntryFilter;
+use fuzzy::ignore::IgnoreStack;
+use fuzzy::options::ScannerOptions;
+
+const IN_MOVED_FROM: u32 = 0x40;
+const IN_MOVED_TO: u32 = 0x80;
+const IN_CREATE: u32 = 0x100;
+const IN_DELETE: u32 = 0x200;
+const IN_DELETE_SELF: u32 = 0x400;
+const IN_MOVE_SELF: u32 = 0x800;
+const IN_Q_OVERFLOW: u32 = 0x4000;
+const IN_IGNORED: u32 = 0x8000;
+const IN_ONLYDIR: u32 = 0x1000000;
+const WATCH_MASK: u32 = IN_CREATE | IN_DELETE | IN_MOVED_FROM | IN_MOVED_TO | IN_DELETE_SELF | IN_MOVE_SELF | IN_ONLYDIR;
+// wd, mask, cookie and the length of the name that follows
+const EVENT_HEADER_SIZE: usize = 16;
+const BUFFER_SIZE: usize = 64 * 1024;
+
+// What changed below a watched root, as the same absolute paths `DirectoryScanner` sends.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Added(Vec<OsString>),
+    // a file, or a directory and everything below it
+    Removed(OsString),
+    // events were dropped, only a new scan gives the full picture again
+    Rescan,
+}
+
+struct WatchedDirectory {
+    path: PathBuf,
+    depth: usize,
+    // the rules for the entries of this directory, its own ignore files included
+    ignore_stack: Option<IgnoreStack>,
+}
+
+// Watches every directory a scan of the root with the same options descends into.
+// Symbolic links to directories are not watched inside, even when followed. A directory
+// that can't be watched, most often because `fs.inotify.max_user_watches` is reached, is
+// an error rather than a part of the tree that silently goes stale. Clones share the
+// watches, so one can `wait` while another starts watching hidden directories.
+#[derive(Clone)]
+pub struct TreeWatcher {
+    fd: c_int,
+    tree: Arc<Mutex<WatchedTree>>,
+}
+
+struct WatchedTree {
+    fd: c_int,
+    options: Arc<ScannerOptions>,
+    filter: Arc<EntryFilter>,
+    directories: HashMap<c_int, WatchedDirectory>,
+    // hidden directories left out while hidden entries aren't, with the rules of the
+    // directory they are in
+    hidden_directories: Vec<(PathBuf, Option<IgnoreStack>, usize)>,
+    // where the symbolic links added are recorded, see `record_links`
+    links: Option<Arc<Mutex<Links>>>,
+}
+
+impl TreeWatcher {
+
+    pub fn new(root_dir: &Path, options: Arc<ScannerOptions>) -> io::Result<TreeWatcher> {
+        let fd = try!(inotify::init());
+        let ignore_stack = if options.respect_ignore_files { Some(IgnoreStack::for_root(root_dir)) } else { None };
+        let filter = Arc::new(EntryFilter::new(&options.filters, root_dir));
+        let mut tree = WatchedTree { fd: fd, options: options, filter: filter, directories: HashMap::new(), hidden_directories: vec![], links: None };
+        try!(tree.watch_tree(root_dir, ignore_stack, 0));
+        Ok(TreeWatcher { fd: fd, tree: Arc::new(Mutex::new(tree)) })
+    }
+
+    // Blocks until something below the root changed. Fails once a new directory can't be
+    // watched, the watcher is no use after that.
+    pub fn wait(&self) -> io::Result<Vec<Change>> {
+        let mut buffer = vec![0; BUFFER_SIZE];
+        let length = try!(inotify::read_events(self.fd, &mut buffer));
+        let mut tree = self.tree.lock().unwrap();
+        let mut changes = vec![];
+        let mut offset = 0;
+        while offset + EVENT_HEADER_SIZE <= length {
+            let wd = native_u32(&buffer[offset..]) as c_int;
+            let mask = native_u32(&buffer[(offset + 4)..]);
+            let name_length = native_u32(&buffer[(offset + 12)..]) as usize;
+            let name = &buffer[(offset + EVENT_HEADER_SIZE)..(offset + EVENT_HEADER_SIZE + name_length)];
+            // the name is padded with NULs
+            let name = &name[..name.iter().position(|byte| *byte == 0).unwrap_or(name.len())];
+            offset += EVENT_HEADER_SIZE + name_length;
+            if mask & IN_Q_OVERFLOW != 0 {
+                changes.push(Change::Rescan);
+            } else if mask & IN_IGNORED != 0 {
+                tree.directories.remove(&wd);
+            } else if mask & (IN_DELETE_SELF | IN_MOVE_SELF) != 0 {
+                changes.extend(tree.forget(wd));
+            } else if let Some(change) = try!(tree.change(wd, mask, OsStr::from_bytes(name))) {
+                changes.push(change);
+            }
+        }
+        Ok(changes)
+    }
+
+    // The symbolic links among what is added are recorded in `links` before it is returned,
+    // like `DirectoryScanner::record_links` does.
+    pub fn record_links(&self, links: Arc<Mutex<Links>>) {
+        self.tree.lock().unwrap().links = Some(links);
+    }
+
+    // Watches the hidden directories too from now on, like a scan with hidden entries.
+    pub fn watch_hidden(&self) -> io::Result<()> {
+        let mut tree = self.tree.lock().unwrap();
+        if tree.options.include_hidden {
+            return Ok(());
+        }
+        let mut options = (*tree.options).clone();
+        options.include_hidden = true;
+        tree.options = Arc::new(options);
+        let hidden_directories = mem::replace(&mut tree.hidden_directories, vec![]);
+        for (dir, ignore_stack, depth) in hidden_directories {
+            try!(tree.watch_tree(&dir, ignore_stack, depth));
+        }
+        Ok(())
+    }
+}
+
+impl WatchedTree {
+
+    fn change(&mut self, wd: c_int, mask: u32, name: &OsStr) -> io::Result<Option<Change>> {
+        let (path, depth, ignore_stack) = match self.directories.get(&wd) {
+            Some(directory) => (directory.path.join(name), directory.depth + 1, directory.ignore_stack.clone()),
+            None => return Ok(None),
+        };
+        if mask & (IN_DELETE | IN_MOVED_FROM) != 0 {
+            return Ok(Some(Change::Removed(path.into_os_string())));
+        }
+        let filetype = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata.file_type(),
+            Err(_) => return Ok(None),
+        };
+        let kind = directory_scanner::entry_kind(&path, filetype, self.options.follow_symlinks);
+        let is_dir = kind == EntryKind::Directory;
+        if directory_scanner::is_ignored(&ignore_stack, &path, is_dir) || self.filter.excludes(&path, kind) {
+            return Ok(None);
+        }
+        if !self.options.include_hidden && directory_scanner::is_hidden(&path) {
+            if is_dir && self.can_descend(depth) {
+                self.hidden_directories.push((path, ignore_stack, depth));
+            }
+            return Ok(None);
+        }
+        if filetype.is_symlink() {
+            if let Some(ref links) = self.links {
+                links.lock().unwrap().extend(directory_scanner::link(&path));
+            }
+        }
+        let mut added = vec![];
+        if depth >= self.options.min_depth && self.options.types.includes(kind) && self.filter.lists(&path, kind, self.options.follow_symlinks) {
+            added.push(directory_scanner::result_path(&path, kind));
+        }
+        if is_dir && self.can_descend(depth) {
+            try!(self.watch_tree(&path, ignore_stack, depth));
+            added.extend(self.scan(&path, depth));
+        }
+        Ok(if added.is_empty() { None } else { Some(Change::Added(added)) })
+    }
+
+    // A directory moved within the tree was watched again under its new path when it
+    // arrived there. One that was moved out or deleted stops being watched, along with
+    // everything below it, and the root going away removes everything.
+    fn forget(&mut self, wd: c_int) -> Option<Change> {
+        let (path, depth) = match self.directories.get(&wd) {
+            Some(directory) => (directory.path.clone(), directory.depth),
+            None => return None,
+        };
+        if fs::metadata(&path).map(|metadata| metadata.is_dir()).unwrap_or(false) {
+            return None;
+        }
+        let forgotten: Vec<c_int> = self.directories.iter()
+            .filter(|&(_, directory)| directory.path.starts_with(&path))
+            .map(|(wd, _)| *wd)
+            .collect();
+        for wd in forgotten {
+            self.directories.remove(&wd);
+            inotify::remove_watch(self.fd, wd);
+        }
+        if depth == 0 { Some(Change::Removed(path.into_os_string())) } else { None }
+    }
+
+    // Directories that vanish or can't be read while the tree is walked are left out like a
+    // scan would, any other failure to watch one is returned.
+    fn watch_tree(&mut self, dir: &Path, ignore_stack: Option<IgnoreStack>, depth: usize) -> io::Result<()> {
+        let wd = match inotify::add_watch(self.fd, dir, WATCH_MASK) {
+            Ok(wd) => wd,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound || error.kind() == io::ErrorKind::PermissionDenied => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        let ignore_stack = ignore_stack.map(|stack| stack.descend(dir));
+        self.directories.insert(wd, WatchedDirectory { path: dir.to_path_buf(), depth: depth, ignore_stack: ignore_stack.clone() });
+        let read_dir = match fs::read_dir(dir) {
+            Ok(read_dir) => read_dir,
+            Err(_) => return Ok(()),
+        };
+        for entry in read_dir.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let is_dir = entry.file_type().map(|filetype| filetype.is_dir()).unwrap_or(false);
+            if !is_dir || !self.can_descend(depth + 1) {
+                continue;
+            }
+            if directory_scanner::is_ignored(&ignore_stack, &path, true) || self.filter.excludes(&path, EntryKind::Directory) {
+                continue;
+            }
+            if !self.options.include_hidden && directory_scanner::is_hidden(&path) {
+                self.hidden_directories.push((path, ignore_stack.clone(), depth + 1));
+                continue;
+            }
+            try!(self.watch_tree(&path, ignore_stack.clone(), depth + 1));
+        }
+        Ok(())
+    }
+
+    // The files of a new directory, as a scan from the root would have found them.
+    fn scan(&self, dir: &Path, depth: usize) -> Vec<OsString> {
+        let mut options = (*self.options).clone();
+        options.max_depth = options.max_depth.map(|max_depth| max_depth.saturating_sub(depth));
+        options.min_depth = options.min_depth.saturating_sub(depth);
+        DirectoryScanner::collect_with_filter(dir.to_path_buf(), Arc::new(options), self.filter.clone(), self.links.clone())
+    }
+
+    fn can_descend(&self, depth: usize) -> bool {
+        self.options.max_depth.map_or(true, |max_depth| depth < max_depth)
+    }
+}
+
+impl Drop for WatchedTree {
+
+    fn drop(&mut self) {
+        inotify::close_fd(self.fd);
+    }
+}
+
+// The kernel writes events in the byte order of the machine.
+fn native_u32(bytes: &[u8]) -> u32 {
+    u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::raw::{c_char, c_int, c_void};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    const IN_CLOEXEC: c_int = 0o2000000;
+    const ENOSPC: i32 = 28;
+
+    extern "C" {
+        fn inotify_init1(flags: c_int) -> c_int;
+        fn inotify_add_watch(fd: c_int, pathname: *const c_char, mask: u32) -> c_int;
+        fn inotify_rm_watch(fd: c_int, wd: c_int) -> c_int;
+        fn read(fd: c_int, buf: *mut c_void, count: usize) -> isize;
+        fn close(fd: c_int) -> c_int;
+    }
+
+    pub fn init() -> io::Result<c_int> {
+        let fd = unsafe { inotify_init1(IN_CLOEXEC) };
+        if fd < 0 { Err(io::Error::last_os_error()) } else { Ok(fd) }
+    }
+
+    pub fn add_watch(fd: c_int, path: &Path, mask: u32) -> io::Result<c_int> {
+        let path = match CString::new(path.as_os_str().as_bytes()) {
+            Ok(path) => path,
+            Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "path contains a NUL byte")),
+        };
+        let wd = unsafe { inotify_add_watch(fd, path.as_ptr(), mask) };
+        if wd >= 0 {
+            return Ok(wd);
+        }
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() == Some(ENOSPC) {
+            Err(io::Error::new(io::ErrorKind::Other, "too many directories to watch, raise fs.inotify.max_user_watches"))
+        } else {
+            Err(error)
+        }
+    }
+
+    pub fn remove_watch(fd: c_int, wd: c_int) {
+        unsafe { inotify_rm_watch(fd, wd); }
+    }
+
+    pub fn read_events(fd: c_int, buffer: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let length = unsafe { read(fd, buffer.as_mut_ptr() as *mut c_void, buffer.len()) };
+            if length >= 0 {
+                return Ok(length as usize);
+            }
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+    }
+
+    pub fn close_fd(fd: c_int) {
+        unsafe { close(fd); }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod inotify {
+    use std::io;
+    use std::os::raw::c_int;
+    use std::path::Path;
+
+    fn unsupported() -> io::Error {
+        io::Error::new(io::ErrorKind::Other, "watching for changes needs inotify, which is Linux only")
+    }
+
+    pub fn init() -> io::Result<c_int> {
+        Err(unsupported())
+    }
+
+    pub fn add_watch(_: c_int, _: &Path, _: u32) -> io::Result<c_int> {
+        Err(unsupported())
+    }
+
+    pub fn remove_watch(_: c_int, _: c_int) {}
+
+    pub fn read_events(_: c_int, _: &mut [u8]) -> io::Result<usize> {
+        Err(unsupported())
+    }
+
+    pub fn close_fd(_: c_int) {}
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn created_and_removed_files_are_reported() {
+    use std::env;
+    use std::fs::File;
+    let root_dir = env::temp_dir().join(format!("fuzzy_watcher_test_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root_dir);
+    fs::create_dir_all(&root_dir).unwrap();
+    let watcher = TreeWatcher::new(&root_dir, Arc::new(ScannerOptions::new())).unwrap();
+    File::create(root_dir.join(".hidden")).unwrap();
+    File::create(root_dir.join("first")).unwrap();
+    let file = root_dir.join("first").into_os_string();
+    assert_eq!(watcher.wait().unwrap(), vec![Change::Added(vec![file.clone()])]);
+    fs::remove_file(&file).unwrap();
+    assert_eq!(watcher.wait().unwrap(), vec![Change::Removed(file)]);
+    let _ = fs::remove_dir_all(&root_dir);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn directories_moved_out_of_the_tree_are_no_longer_watched() {
+    use std::env;
+    use std::fs::File;
+    let dir = env::temp_dir().join(format!("fuzzy_watcher_move_test_{}", std::process::id()));
+    let root_dir = dir.join("root");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(root_dir.join("moved").join("below")).unwrap();
+    let watcher = TreeWatcher::new(&root_dir, Arc::new(ScannerOptions::new())).unwrap();
+    fs::rename(root_dir.join("moved"), dir.join("moved")).unwrap();
+    File::create(dir.join("moved").join("below").join("outside")).unwrap();
+    File::create(root_dir.join("inside")).unwrap();
+    let mut changes = vec![];
+    while !changes.contains(&Change::Added(vec![root_dir.join("inside").into_os_string()])) {
+        changes.extend(watcher.wait().unwrap());
+    }
+    assert_eq!(changes, vec![Change::Removed(root_dir.join("moved").into_os_string()), Change::Added(vec![root_dir.join("inside").into_os_string()])]);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn hidden_directories_are_watched_once_asked_for() {
+    use std::env;
+    use std::fs::File;
+    let root_dir = env::temp_dir().join(format!("fuzzy_watcher_hidden_test_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root_dir);
+    fs::create_dir_all(root_dir.join(".config")).unwrap();
+    let watcher = TreeWatcher::new(&root_dir, Arc::new(ScannerOptions::new())).unwrap();
+    fs::create_dir(root_dir.join(".cache")).unwrap();
+    File::create(root_dir.join("visible")).unwrap();
+    assert_eq!(watcher.wait().unwrap(), vec![Change::Added(vec![root_dir.join("visible").into_os_string()])]);
+    watcher.watch_hidden().unwrap();
+    File::create(root_dir.join(".config").join("first")).unwrap();
+    assert_eq!(watcher.wait().unwrap(), vec![Change::Added(vec![root_dir.join(".config/first").into_os_string()])]);
+    File::create(root_dir.join(".cache").join("second")).unwrap();
+    assert_eq!(watcher.wait().unwrap(), vec![Change::Added(vec![root_dir.join(".cache/second").into_os_string()])]);
+    let _ = fs::remove_dir_all(&root_dir);
+}
+
+#[test]
+fn event_fields_are_read_in_the_byte_order_of_the_machine() {
+    let value: u32 = 0x01020304;
+    assert_eq!(native_u32(&value.to_ne_bytes()), value);
+}
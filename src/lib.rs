@@ -3,6 +3,9 @@ extern crate regex;
 extern crate crossbeam;
 
 use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::io::{self, Write};
 use std::thread;
 use std::sync::{Arc, Mutex};
 use std::sync::mpsc::*;
@@ -11,49 +14,101 @@ mod fuzzy;
 
 use fuzzy::search_phrase::SearchPhrase;
 use fuzzy::terminal::Terminal;
-use fuzzy::file_finder::FileFinder;
+use fuzzy::file_finder::{self, FileFinder};
 use fuzzy::event_service::EventService;
+use fuzzy::options::{Options, Command, USAGE};
+use fuzzy::stdin_reader;
+use fuzzy::filter;
+use fuzzy::daemon;
+use fuzzy::history::History;
+use fuzzy::frecency::FrecencyDatabase;
 use std::sync::atomic::{Ordering, AtomicBool};
 
+enum Outcome {
+    Selected(Vec<OsString>),
+    NoMatch,
+    Aborted,
+}
+
+impl Outcome {
+
+    fn exit_code(&self) -> i32 {
+        match *self {
+            Outcome::Selected(_) => 0,
+            Outcome::NoMatch => 1,
+            Outcome::Aborted => 130,
+        }
+    }
+}
+
 struct App {
+    options: Options,
     threads: u8,
     terminal: Arc<Terminal>,
     file_finder: Arc<Mutex<FileFinder>>,
-    event_service: Arc<EventService>,
     rx: std::sync::mpsc::Receiver<usize>,
     tx: std::sync::mpsc::Sender<usize>,
-    app_finished: Arc<AtomicBool>
+    app_finished: Arc<AtomicBool>,
+    search_phrase: Arc<Mutex<SearchPhrase>>,
+    history: Arc<Mutex<History>>,
+    frecency: FrecencyDatabase
 }
 
 impl App {
 
-    pub fn new() -> App {
+    pub fn new(options: Options) -> App {
         let app_finished = Arc::new(AtomicBool::new(false));
         let (tx, rx) = channel();
         let event_service = Arc::new(EventService::new());
-        let terminal = Terminal::new(event_service.clone());
-        let file_finder = FileFinder::new(terminal.clone(), event_service.clone());
+        let mut search_phrase = SearchPhrase::from_string(options.query.clone(), event_service.clone());
+        search_phrase.exact = options.exact;
+        search_phrase.regex_mode = options.regex_mode;
+        search_phrase.case = options.case;
+        search_phrase.include_hidden = options.scanner.include_hidden;
+        if !options.query.is_empty() {
+            event_service.trigger_search_phrase_changed(search_phrase.clone());
+        }
+        let search_phrase = Arc::new(Mutex::new(search_phrase));
+        let terminal = Terminal::new(event_service.clone(), options.layout, options.preview_command.clone(), search_phrase.clone(), !options.read_stdin);
+        let file_finder = FileFinder::new(terminal.clone(), event_service.clone(), options.scanner.clone(), options.use_daemon, options.cache);
         {
             let tx = event_service.tx.clone();
             file_finder.lock().unwrap().add_subscriber_channel(tx);
         }
+        let frecency = FrecencyDatabase::load(FrecencyDatabase::default_path());
+        if !options.read_stdin {
+            let roots = file_finder::scan_roots(&options.root_dirs);
+            file_finder.lock().unwrap().set_boosts(frecency.boosts(&roots, FrecencyDatabase::now()));
+        }
+        let history_path = options.history.clone().or(History::default_path());
+        let history = History::load(history_path, options.history_size);
         App { 
+            options: options,
             threads: 0,
             terminal: terminal,
             file_finder: file_finder,
-            event_service: event_service,
             rx: rx,
             tx: tx,
             app_finished: app_finished,
+            search_phrase: search_phrase,
+            history: Arc::new(Mutex::new(history)),
+            frecency: frecency,
         }
     }
 
-    pub fn start(&mut self) -> String {
+    pub fn start(&mut self) -> Outcome {
         self.find_files();
         self.capture_user_input();
         self.prepare_terminal();
         self.wait_until_exit();
-        self.get_found_file()
+        let outcome = self.get_found_file();
+        if let Outcome::Selected(ref found_files) = outcome {
+            self.remember_query();
+            if !self.options.read_stdin {
+                self.remember_selection(found_files);
+            }
+        }
+        outcome
     }
 
     // --------- private methods ----------- //
@@ -65,22 +120,27 @@ impl App {
     fn find_files(&mut self) {
         let file_finder = self.file_finder.clone();
         let tx = self.tx.clone();
+        let root_dirs = self.options.root_dirs.clone();
+        let read_stdin = self.options.read_stdin;
         self.threads += 1;
         thread::spawn(move|| {
             let mut locked_local_file_finder = file_finder.lock().unwrap();
-            locked_local_file_finder.start(&env::current_dir().unwrap());
+            if read_stdin {
+                locked_local_file_finder.start_from_stdin();
+            } else {
+                locked_local_file_finder.start(&root_dirs);
+            }
             tx.send(1)
         });
     }
 
     fn capture_user_input(&mut self) {
-        let search_phrase = Arc::new(Mutex::new(SearchPhrase::init(self.event_service.clone())));
         let tx = self.tx.clone();
-        let local_search_phrase = search_phrase.clone();
+        let local_history = self.history.clone();
         let local_terminal = self.terminal.clone();
         self.threads += 1;
         thread::spawn(move || {
-            local_terminal.on_stdin(local_search_phrase);
+            local_terminal.on_stdin(local_history);
             tx.send(1)
         });
 
@@ -93,17 +153,90 @@ impl App {
         }
     }
 
-    fn get_found_file(&self) -> String {
-        if self.terminal.has_highlighted_result() {
-            self.terminal.get_highlighted_result()
+    fn remember_query(&self) {
+        let query = self.search_phrase.lock().unwrap().content.clone();
+        if let Err(error) = self.history.lock().unwrap().add(&query) {
+            let _ = writeln!(io::stderr(), "fuzzy: could not save the query history: {}", error);
+        }
+    }
+
+    fn remember_selection(&mut self, found_files: &Vec<OsString>) {
+        let roots = file_finder::scan_roots(&self.options.root_dirs);
+        let now = FrecencyDatabase::now();
+        for found_file in found_files.iter() {
+            self.frecency.record(&roots, found_file, now);
+        }
+        if let Err(error) = self.frecency.save(now) {
+            let _ = writeln!(io::stderr(), "fuzzy: could not save the frecency database: {}", error);
+        }
+    }
+
+    fn get_found_file(&self) -> Outcome {
+        if self.terminal.was_aborted() {
+            return Outcome::Aborted;
+        }
+        let found_files = self.terminal.get_selected_results();
+        if found_files.is_empty() {
+            Outcome::NoMatch
         } else {
-            String::new()
+            Outcome::Selected(found_files)
         }
     }
 }
 
 pub fn initialize() {
-    let found_file = App::new().start();
-    println!("{}", found_file);
-    std::process::exit(0);
+    let mut options = match Options::parse(env::args().skip(1).collect()) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            std::process::exit(0);
+        }
+        Ok(Command::Daemon(options)) => {
+            check_root_dirs(&options.root_dirs);
+            std::process::exit(daemon::run(&options.root_dirs, &options.scanner));
+        }
+        Ok(Command::ClearFrecency) => std::process::exit(update_frecency(|frecency| frecency.clear())),
+        Ok(Command::PruneFrecency) => std::process::exit(update_frecency(|frecency| frecency.prune(FrecencyDatabase::now()))),
+        Ok(Command::Version) => {
+            println!("fuzzy {}", env!("CARGO_PKG_VERSION"));
+            std::process::exit(0);
+        }
+        Err(error) => {
+            let _ = writeln!(io::stderr(), "fuzzy: {}\n\n{}", error, USAGE);
+            std::process::exit(2);
+        }
+    };
+    check_root_dirs(&options.root_dirs);
+    options.read_stdin = options.root_dirs.is_empty() && stdin_reader::stdin_is_piped();
+    if let Some(ref query) = options.filter {
+        std::process::exit(filter::run(&options, query));
+    }
+    let print0 = options.print0;
+    let outcome = App::new(options).start();
+    if let Outcome::Selected(ref found_files) = outcome {
+        let stdout = io::stdout();
+        let _ = filter::write_results(&mut stdout.lock(), found_files, print0);
+    }
+    std::process::exit(outcome.exit_code());
+}
+
+fn check_root_dirs(root_dirs: &Vec<PathBuf>) {
+    for root_dir in root_dirs.iter() {
+        if !root_dir.is_dir() {
+            let _ = writeln!(io::stderr(), "fuzzy: '{}' is not a directory", root_dir.display());
+            std::process::exit(2);
+        }
+    }
+}
+
+fn update_frecency<F: Fn(&mut FrecencyDatabase)>(update: F) -> i32 {
+    let mut frecency = FrecencyDatabase::load(FrecencyDatabase::default_path());
+    update(&mut frecency);
+    match frecency.save(FrecencyDatabase::now()) {
+        Ok(_) => 0,
+        Err(error) => {
+            let _ = writeln!(io::stderr(), "fuzzy: could not save the frecency database: {}", error);
+            1
+        }
+    }
 }